@@ -1,6 +1,12 @@
-use serde::{Deserialize, Serialize};
+use crate::{EncryptMode, LpkType, Result};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
 
-#[derive(Default, Serialize, Deserialize)]
+/// Steam Workshop LPK 旁边的 config.json
+///
+/// 缺少的字段使用默认值, 无法识别的字段按原有顺序保存在 `extra` 中, 序列化时原样写回.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, remote = "Self")]
 pub struct LpkConfig {
     #[serde(rename = "lpkFile")]
     pub lpk_file: String,
@@ -18,30 +24,95 @@ pub struct LpkConfig {
     pub description: String,
     #[serde(rename = "metaData")]
     pub meta_data: String,
+    /// 显式的 `"key": null` 保存在 `extra` 中, 与缺少这个字段区分
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub key: Option<String>,
+    /// 无法识别的字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+impl<'de> Deserialize<'de> for LpkConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let null_key = value.get("key").is_some_and(Value::is_null);
+        let mut config = LpkConfig::deserialize(value).map_err(D::Error::custom)?;
+        if null_key {
+            // key 在无法识别的字段之前序列化
+            config.extra.shift_insert(0, "key".to_string(), Value::Null);
+        }
+        Ok(config)
+    }
+}
+
+impl Serialize for LpkConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        // 设置了 key 时不再写出之前保存的 null
+        match self.key.is_some() && self.extra.contains_key("key") {
+            true => {
+                let mut config = self.clone();
+                config.extra.remove("key");
+                LpkConfig::serialize(&config, serializer)
+            }
+            false => LpkConfig::serialize(self, serializer),
+        }
+    }
+}
+
+impl LpkConfig {
+    /// 从 config.json 的原始内容解析配置, 兼容带 BOM 的文件
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// LPK 中的 config.mlve
+///
+/// 缺少的字段使用默认值, 无法识别的字段 (背景, 语音和各版本特有的设置等) 保存在 `extra` 中.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MLveConfig {
     #[serde(rename = "type")]
-    pub r#type: String,
+    pub r#type: LpkType,
     pub name: String,
     pub id: String,
-    pub encrypt: String,
+    pub encrypt: EncryptMode,
     pub version: String,
     pub list: Vec<LpkCharacter>,
+    /// 无法识别的字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl MLveConfig {
+    /// 从 config.mlve 的原始内容解析配置, 兼容带 BOM 的文件
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+        Ok(serde_json::from_slice(bytes)?)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 角色的一套服装
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LpkCostume {
     pub name: String,
     pub path: String,
+    /// 无法识别的字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// config.mlve 中的角色
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LpkCharacter {
     pub id: String,
     pub character: String,
     pub avatar: String,
     pub costume: Vec<LpkCostume>,
+    /// 无法识别的字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
@@ -1,63 +1,250 @@
-use std::fmt::{Display, Formatter};
+use crate::KeyScheme;
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
+
+/// 出错时所处的处理阶段
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorStage {
+    /// 打开LPK文件或压缩包
+    Open,
+    /// 读取 config.mlve 或 config.json
+    Config,
+    /// 读取并解密条目
+    Decrypt,
+    /// 写入输出文件
+    Write,
+    /// 没有记录阶段
+    Unknown,
+}
+
+impl ErrorStage {
+    /// 稳定的机器可读名称, [`Display`] 输出中文名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorStage::Open => "open",
+            ErrorStage::Config => "config",
+            ErrorStage::Decrypt => "decrypt",
+            ErrorStage::Write => "write",
+            ErrorStage::Unknown => "unknown",
+        }
+    }
+}
+
+impl Display for ErrorStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorStage::Open => f.write_str("打开"),
+            ErrorStage::Config => f.write_str("配置"),
+            ErrorStage::Decrypt => f.write_str("解密"),
+            ErrorStage::Write => f.write_str("写入"),
+            ErrorStage::Unknown => f.write_str("未知"),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum LpkError {
-    IoError { path: String, message: String },
+    /// 文件读写失败, `path` 是出错的文件或条目
+    IoError {
+        path: String,
+        stage: ErrorStage,
+        source: Arc<std::io::Error>,
+    },
 
-    ZipError(String),
+    /// 压缩包格式错误, `entry` 是出错的条目, 打开压缩包时为LPK路径
+    ZipError {
+        entry: String,
+        stage: ErrorStage,
+        source: Arc<zip::result::ZipError>,
+    },
 
-    DecodeError { format: String, message: String },
+    /// 内容无法解析, `path` 是出错的文件或条目
+    DecodeError {
+        format: String,
+        path: String,
+        stage: ErrorStage,
+        message: String,
+        source: Option<Arc<serde_json::Error>>,
+    },
 
     ConfigMissing,
 
+    ModelMissing(String),
+
     UnsupportedLpkType(String),
 
-    DecryptionFailed(String),
+    /// 解密结果校验失败, 通常是密钥错误或者不支持的加密方式
+    DecryptionFailed {
+        entry: String,
+        scheme: KeyScheme,
+    },
+
+    UnsafePath(String),
+
+    /// 通过 [`CancellationToken`](crate::CancellationToken) 取消了解压
+    Cancelled,
 
     UnknownError,
 }
 
+impl LpkError {
+    /// 稳定的机器可读错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            LpkError::IoError { .. } => "io",
+            LpkError::ZipError { .. } => "zip",
+            LpkError::DecodeError { .. } => "decode",
+            LpkError::ConfigMissing => "config_missing",
+            LpkError::ModelMissing(_) => "model_missing",
+            LpkError::UnsupportedLpkType(_) => "unsupported_lpk_type",
+            LpkError::DecryptionFailed { .. } => "decryption_failed",
+            LpkError::UnsafePath(_) => "unsafe_path",
+            LpkError::Cancelled => "cancelled",
+            LpkError::UnknownError => "unknown",
+        }
+    }
+
+    /// 出错时所处的阶段
+    pub fn stage(&self) -> ErrorStage {
+        match self {
+            LpkError::IoError { stage, .. } | LpkError::ZipError { stage, .. } | LpkError::DecodeError { stage, .. } => *stage,
+            LpkError::ConfigMissing => ErrorStage::Config,
+            LpkError::DecryptionFailed { .. } => ErrorStage::Decrypt,
+            LpkError::UnsafePath(_) => ErrorStage::Write,
+            _ => ErrorStage::Unknown,
+        }
+    }
+
+    /// 出错的文件路径或条目名, 未知时返回 `None`
+    pub fn path(&self) -> Option<&str> {
+        let path = match self {
+            LpkError::IoError { path, .. } | LpkError::DecodeError { path, .. } => path,
+            LpkError::ZipError { entry, .. } | LpkError::DecryptionFailed { entry, .. } => entry,
+            LpkError::ModelMissing(path) | LpkError::UnsafePath(path) => path,
+            _ => return None,
+        };
+        if path.is_empty() {
+            None
+        }
+        else {
+            Some(path)
+        }
+    }
+
+    /// 英文错误信息, [`Display`] 输出中文错误信息
+    pub fn english(&self) -> String {
+        match self {
+            LpkError::IoError { path, stage, source } => format!("IO error during {}: {path} {source}", stage.as_str()),
+            LpkError::ZipError { entry, stage, source } => format!("Zip error during {}: {entry} {source}", stage.as_str()),
+            LpkError::DecodeError { format, path, stage, message, .. } => {
+                format!("Decode error during {}: {format} {path} {message}", stage.as_str())
+            }
+            LpkError::ConfigMissing => "Configuration file is missing".to_string(),
+            LpkError::ModelMissing(e) => format!("Model file is missing: {e}"),
+            LpkError::UnsupportedLpkType(e) => format!("Unsupported LPK type: {e}"),
+            LpkError::DecryptionFailed { entry, scheme } => format!("Decryption failed: {entry} (scheme {scheme:?})"),
+            LpkError::UnsafePath(e) => format!("Unsafe output path: {e}"),
+            LpkError::Cancelled => "Extraction cancelled".to_string(),
+            LpkError::UnknownError => "Unknown error".to_string(),
+        }
+    }
+
+    /// 补充出错的阶段和路径, 已经记录的信息不会被覆盖
+    pub fn with_context(mut self, new_stage: ErrorStage, new_path: impl Display) -> Self {
+        match &mut self {
+            LpkError::IoError { path, stage, .. }
+            | LpkError::ZipError { entry: path, stage, .. }
+            | LpkError::DecodeError { path, stage, .. } => {
+                if *stage == ErrorStage::Unknown {
+                    *stage = new_stage;
+                }
+                if path.is_empty() {
+                    *path = new_path.to_string();
+                }
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
 impl Display for LpkError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            LpkError::IoError { path, message } => {
-                write!(f, "IO错误: {path} {message}", path = path, message = message)
+            LpkError::IoError { path, stage, source } => {
+                write!(f, "IO错误({stage}): {path} {source}")
+            }
+            LpkError::ZipError { entry, stage, source } => {
+                write!(f, "Zip错误({stage}): {entry} {source}")
             }
-            LpkError::ZipError(e) => {
-                write!(f, "Zip错误: {e}", e = e)
+            LpkError::DecodeError { format, path, stage, message, .. } => {
+                write!(f, "解码错误({stage}): {format} {path} {message}")
             }
-            LpkError::DecodeError { format, message } => {
-                write!(f, "解码错误: {format} {message}", format = format, message = message)
+            LpkError::ConfigMissing => f.write_str("配置文件缺失"),
+            LpkError::ModelMissing(e) => {
+                write!(f, "模型文件缺失: {e}", e = e)
             }
-            LpkError::ConfigMissing {} => f.write_str("配置文件缺失"),
             LpkError::UnsupportedLpkType(e) => {
                 write!(f, "不支持的LPK类型: {e}", e = e)
             }
-            LpkError::DecryptionFailed(e) => {
-                write!(f, "解密失败: {e}", e = e)
+            LpkError::DecryptionFailed { entry, scheme } => {
+                write!(f, "解密失败: {entry} (密钥规则 {scheme:?})")
             }
-            LpkError::UnknownError {} => f.write_str("未知错误"),
+            LpkError::UnsafePath(e) => {
+                write!(f, "不安全的输出路径: {e}", e = e)
+            }
+            LpkError::Cancelled => f.write_str("解压已取消"),
+            LpkError::UnknownError => f.write_str("未知错误"),
+        }
+    }
+}
+
+impl Error for LpkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LpkError::IoError { source, .. } => Some(source.as_ref()),
+            LpkError::ZipError { source, .. } => Some(source.as_ref()),
+            LpkError::DecodeError { source: Some(source), .. } => Some(source.as_ref()),
+            _ => None,
         }
     }
 }
 
 impl From<std::io::Error> for LpkError {
-    #[track_caller]
     fn from(e: std::io::Error) -> Self {
-        let loc = std::panic::Location::caller();
-        LpkError::IoError { path: loc.to_string(), message: e.to_string() }
+        LpkError::IoError { path: String::new(), stage: ErrorStage::Unknown, source: Arc::new(e) }
     }
 }
 
 impl From<zip::result::ZipError> for LpkError {
     fn from(err: zip::result::ZipError) -> Self {
-        LpkError::ZipError(err.to_string())
+        LpkError::ZipError { entry: String::new(), stage: ErrorStage::Unknown, source: Arc::new(err) }
     }
 }
 
 impl From<serde_json::Error> for LpkError {
     fn from(e: serde_json::Error) -> Self {
-        LpkError::DecodeError { format: "json".to_string(), message: e.to_string() }
+        LpkError::DecodeError {
+            format: "json".to_string(),
+            path: String::new(),
+            stage: ErrorStage::Unknown,
+            message: e.to_string(),
+            source: Some(Arc::new(e)),
+        }
+    }
+}
+
+/// 为错误补充阶段和路径
+pub(crate) trait Context<T> {
+    fn context(self, stage: ErrorStage, path: impl Display) -> Result<T>;
+}
+
+impl<T, E: Into<LpkError>> Context<T> for std::result::Result<T, E> {
+    fn context(self, stage: ErrorStage, path: impl Display) -> Result<T> {
+        self.map_err(|e| e.into().with_context(stage, path))
     }
 }
 
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// 识别文件类型所需读取的最大字节数
+pub const SNIFF_LEN: usize = 16;
+
+/// 根据文件头识别出的文件类型
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FileType {
+    /// PNG 纹理
+    Png,
+    /// JPEG 图片
+    Jpeg,
+    /// GIF 图片
+    Gif,
+    /// WebP 图片
+    Webp,
+    /// Cubism 3+ 模型数据
+    Moc3,
+    /// Cubism 2 模型数据
+    Moc,
+    /// JSON 配置 (模型, 动作, 表情, 物理等)
+    Json,
+    /// Ogg 音频
+    Ogg,
+    /// WAV 音频
+    Wav,
+    /// MP3 音频
+    Mp3,
+    /// MP4 视频
+    Mp4,
+    /// TrueType 字体
+    Ttf,
+    /// OpenType 字体
+    Otf,
+    /// WOFF 字体
+    Woff,
+    /// WOFF2 字体
+    Woff2,
+    /// 无法识别的文件
+    Unknown,
+}
+
+impl FileType {
+    /// 根据解密后数据的开头识别文件类型
+    pub fn sniff(data: &[u8]) -> FileType {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            FileType::Png
+        }
+        else if data.starts_with(b"\xFF\xD8\xFF") {
+            FileType::Jpeg
+        }
+        else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            FileType::Gif
+        }
+        else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+            FileType::Webp
+        }
+        else if data.starts_with(b"MOC3") {
+            FileType::Moc3
+        }
+        else if data.starts_with(b"moc") {
+            FileType::Moc
+        }
+        else if data.starts_with(b"OggS") {
+            FileType::Ogg
+        }
+        else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+            FileType::Wav
+        }
+        else if data.starts_with(b"ID3") || is_mpeg_frame(data) {
+            FileType::Mp3
+        }
+        else if data.get(4..8) == Some(b"ftyp") {
+            FileType::Mp4
+        }
+        else if data.starts_with(b"\x00\x01\x00\x00") || data.starts_with(b"true") {
+            FileType::Ttf
+        }
+        else if data.starts_with(b"OTTO") {
+            FileType::Otf
+        }
+        else if data.starts_with(b"wOFF") {
+            FileType::Woff
+        }
+        else if data.starts_with(b"wOF2") {
+            FileType::Woff2
+        }
+        else if is_json(data) {
+            FileType::Json
+        }
+        else {
+            FileType::Unknown
+        }
+    }
+
+    /// 文件类型对应的扩展名, 无法识别时为 `bin`
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileType::Png => "png",
+            FileType::Jpeg => "jpg",
+            FileType::Gif => "gif",
+            FileType::Webp => "webp",
+            FileType::Moc3 => "moc3",
+            FileType::Moc => "moc",
+            FileType::Json => "json",
+            FileType::Ogg => "ogg",
+            FileType::Wav => "wav",
+            FileType::Mp3 => "mp3",
+            FileType::Mp4 => "mp4",
+            FileType::Ttf => "ttf",
+            FileType::Otf => "otf",
+            FileType::Woff => "woff",
+            FileType::Woff2 => "woff2",
+            FileType::Unknown => "bin",
+        }
+    }
+}
+
+impl Display for FileType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// 跳过 BOM 和空白后以 `{` 或 `[` 开头
+fn is_json(data: &[u8]) -> bool {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    matches!(data.iter().find(|b| !b.is_ascii_whitespace()), Some(b'{' | b'['))
+}
+
+/// MPEG 音频帧头, 除同步位外版本, 层, 比特率和采样率都不能是保留值
+fn is_mpeg_frame(data: &[u8]) -> bool {
+    match data {
+        [0xFF, b1, b2, ..] => {
+            b1 & 0xE0 == 0xE0 && (b1 >> 3) & 3 != 1 && (b1 >> 1) & 3 != 0 && b2 >> 4 != 0xF && (b2 >> 2) & 3 != 3
+        }
+        _ => false,
+    }
+}
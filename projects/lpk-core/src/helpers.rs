@@ -1,4 +1,5 @@
-use std::path::Path;
+use crate::{Keystream, LpkError, Result};
+use std::path::{Path, PathBuf};
 
 /// 计算字符串的MD5哈希值，返回十六进制字符串
 pub fn hashed_filename(s: &str) -> String {
@@ -11,6 +12,56 @@ pub fn safe_mkdir<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
     std::fs::create_dir_all(path)
 }
 
+/// Windows 保留的设备名, 不能用作文件名
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3",
+    "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// 将包内的名称转换为可以安全用作单个文件名的形式，结果为空时使用 `fallback`
+pub fn sanitize_file_name(name: &str, fallback: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            }
+            else {
+                c
+            }
+        })
+        .collect::<String>();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        return fallback.to_string();
+    }
+    let stem = name.split('.').next().unwrap_or(name);
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return format!("_{name}");
+    }
+    name.to_string()
+}
+
+/// 将包内的相对路径拼接到输出目录下，拒绝绝对路径和越过输出目录的路径
+pub fn safe_join(root: &Path, relative: &str) -> Result<PathBuf> {
+    let unsafe_path = || LpkError::UnsafePath(relative.to_string());
+    if relative.starts_with(['/', '\\']) || relative.contains(':') {
+        return Err(unsafe_path());
+    }
+    let mut path = root.to_path_buf();
+    for part in relative.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return Err(unsafe_path()),
+            part => path.push(part),
+        }
+    }
+    if path == root {
+        return Err(unsafe_path());
+    }
+    Ok(path)
+}
+
 /// 生成解密密钥
 pub fn make_key(s: &str) -> i128 {
     let mut ret = 0;
@@ -25,20 +76,16 @@ pub fn make_key(s: &str) -> i128 {
 
 /// 解密数据
 pub fn decrypt(key: i128, data: &[u8]) -> Vec<u8> {
-    let mut ret = Vec::with_capacity(data.len());
-
-    // 按1024字节分片处理
-    for chunk in data.chunks(1024) {
-        let mut k = key;
-        for &byte in chunk {
-            k = (65535 & 2531011 + 214013 * k >> 16) & 0xffffffff;
-            ret.push((k & 0xff) as u8 ^ byte);
-        }
-    }
-
+    let mut ret = data.to_vec();
+    decrypt_in_place(key, &mut ret);
     ret
 }
 
+/// 原地解密数据, 加密与解密是同一个操作
+pub fn decrypt_in_place(key: i128, data: &mut [u8]) {
+    Keystream::new(key).decrypt_in_place(data)
+}
+
 /// 检查文件名是否是加密文件（32位十六进制字符串，可能以.bin或.bin3结尾）
 pub fn is_encrypted_file(s: &str) -> bool {
     if s.len() < 32 {
@@ -67,8 +114,7 @@ pub fn find_encrypted_file(s: &str) -> Option<String> {
 
 /// 从命令字符串中获取加密文件名
 pub fn get_encrypted_file(s: &str) -> Option<String> {
-    if s.starts_with("change_cos ") {
-        let filename = &s["change_cos ".len()..];
+    if let Some(filename) = s.strip_prefix("change_cos ") {
         if is_encrypted_file(filename) {
             return Some(filename.to_string());
         }
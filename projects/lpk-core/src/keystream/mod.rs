@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+/// 密钥流长度, 加密数据每 1024 字节重新从密钥开始
+pub const KEYSTREAM_LEN: usize = 1024;
+
+/// 条目的密钥生成规则
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyScheme {
+    /// 明文存储, 不需要解密
+    Plain,
+    /// 固定使用 0 作为密钥
+    Zero,
+    /// `id + 文件名`, 用于标准 LPK
+    Standard,
+    /// `id + fileId + 文件名 + metaData`, 用于 Steam Workshop LPK
+    Steam,
+    /// 缺少密钥材料, 从已知明文恢复
+    Recovered,
+}
+
+/// 预先计算的解密密钥流
+///
+/// 每个 1024 字节分片都使用同一个密钥重新开始, 所以同一密钥的密钥流对所有分片都相同,
+/// 只需要计算一次, 之后整块异或即可.
+///
+/// 生成器每一步的状态都只有 16 位, 所以整条密钥流完全由第一步的状态 (种子) 决定.
+#[derive(Clone)]
+pub struct Keystream {
+    seed: u16,
+    bytes: [u8; KEYSTREAM_LEN],
+}
+
+impl Keystream {
+    /// 根据 [`make_key`](crate::helpers::make_key) 生成的密钥计算密钥流
+    pub fn new(key: i128) -> Self {
+        Keystream::from_seed(next_state(key) as u16)
+    }
+
+    /// 根据第一步的生成器状态计算密钥流
+    pub fn from_seed(seed: u16) -> Self {
+        let mut bytes = [0; KEYSTREAM_LEN];
+        let mut k = seed as i128;
+        bytes[0] = seed as u8;
+        for byte in bytes.iter_mut().skip(1) {
+            k = next_state(k);
+            *byte = (k & 0xff) as u8;
+        }
+        Keystream { seed, bytes }
+    }
+
+    /// 获取密钥流的种子
+    pub fn seed(&self) -> u16 {
+        self.seed
+    }
+
+    /// 获取密钥流的原始字节
+    pub fn as_bytes(&self) -> &[u8; KEYSTREAM_LEN] {
+        &self.bytes
+    }
+
+    /// 原地解密, `data` 从分片起始位置开始
+    pub fn decrypt_in_place(&self, data: &mut [u8]) {
+        self.decrypt_at(0, data)
+    }
+
+    /// 原地解密从 `offset` 开始的一段数据, 用于流式读取
+    pub fn decrypt_at(&self, offset: u64, data: &mut [u8]) {
+        let start = (offset % KEYSTREAM_LEN as u64) as usize;
+        let (head, tail) = data.split_at_mut(data.len().min(KEYSTREAM_LEN - start));
+        xor(head, &self.bytes[start..]);
+        for chunk in tail.chunks_mut(KEYSTREAM_LEN) {
+            xor(chunk, &self.bytes);
+        }
+    }
+}
+
+/// 线性同余生成器的下一个状态
+#[inline]
+pub(crate) fn next_state(k: i128) -> i128 {
+    (65535 & ((2531011 + 214013 * k) >> 16)) & 0xffffffff
+}
+
+/// 整块异或, 等长切片的逐元素循环可以被编译器向量化
+#[inline]
+fn xor(data: &mut [u8], key: &[u8]) {
+    let key = &key[..data.len()];
+    for (byte, k) in data.iter_mut().zip(key) {
+        *byte ^= k;
+    }
+}
@@ -1,7 +1,29 @@
+mod cancel;
 mod configs;
 mod errors;
+mod file_type;
+mod keystream;
 mod lpk_loader;
+mod lpk_type;
+mod lpk_writer;
+mod sink;
+mod workshop;
 pub mod helpers;
-pub use crate::configs::{LpkConfig, MLveConfig};
-pub use errors::{LpkError, Result};
-pub use lpk_loader::LpkLoader;
\ No newline at end of file
+pub mod recovery;
+pub use cancel::CancellationToken;
+pub use crate::configs::{LpkCharacter, LpkConfig, LpkCostume, MLveConfig};
+pub use errors::{ErrorStage, LpkError, Result};
+pub use file_type::FileType;
+pub use keystream::{KeyScheme, Keystream, KEYSTREAM_LEN};
+pub use lpk_loader::{
+    AssetLayout, EntryKind, EntryReader, ExtractEvent, ExtractObserver, ExtractionPlan, LpkEntry, LpkInfo, LpkLoader, PlannedFile,
+    Selection, INCREMENTAL_MANIFEST, SHARED_ASSETS_DIR,
+};
+#[cfg(feature = "mmap")]
+pub use lpk_loader::MappedLpk;
+#[cfg(feature = "tokio")]
+pub use lpk_loader::AsyncLpkLoader;
+pub use lpk_type::{EncryptMode, LpkType};
+pub use lpk_writer::LpkWriter;
+pub use sink::{DirectorySink, ExtractSink, MemorySink, TarCompression, TarSink, ZipSink};
+pub use workshop::{WorkshopItem, WorkshopManifest, WORKSHOP_APP_ID};
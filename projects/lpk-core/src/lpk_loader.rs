@@ -1,28 +1,67 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
-    io::{Read, Write},
+    io::{Cursor, Read, Seek},
     path::{Path, PathBuf},
 };
 
-use crate::{LpkConfig, MLveConfig};
+use crate::{DirectorySink, EncryptMode, ExtractSink, FileType, KeyScheme, Keystream, LpkConfig, LpkType, MLveConfig};
 use tracing::{debug, error, info, trace, warn};
 use zip::ZipArchive;
 
+#[cfg(feature = "mmap")]
+pub use self::mmap::MappedLpk;
+#[cfg(feature = "tokio")]
+pub use self::nonblocking::AsyncLpkLoader;
+use self::{
+    dedupe::{is_shareable, SharedAssets},
+    events::{ExtractHooks, WriteCallback},
+    incremental::IncrementalState,
+    verify::{verify_model_json, warn_unrecognized},
+};
+pub use self::{
+    dedupe::{AssetLayout, SHARED_ASSETS_DIR},
+    entries::{EntryKind, EntryReader, LpkEntry},
+    events::{ExtractEvent, ExtractObserver},
+    incremental::INCREMENTAL_MANIFEST,
+    info::LpkInfo,
+    plan::{ExtractionPlan, PlannedFile},
+    select::Selection,
+};
+
+mod dedupe;
+mod entries;
+mod events;
 mod extractors;
+mod incremental;
+mod info;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "tokio")]
+mod nonblocking;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod plan;
+mod restore;
+mod select;
+mod verify;
 
 use crate::{
-    errors::{LpkError, Result},
-    helpers::{decrypt, hashed_filename, is_encrypted_file, make_key, safe_mkdir},
+    errors::{Context, ErrorStage, LpkError, Result},
+    helpers::{hashed_filename, is_encrypted_file, make_key, safe_join, safe_mkdir, sanitize_file_name},
+    recovery::recover_keystream,
+    sink::PREALLOCATE_LIMIT,
     LpkError::DecodeError,
 };
 
 /// LPK文件加载器，负责解析和解压LPK文件
-pub struct LpkLoader {
-    /// LPK文件路径
-    lpk_path: PathBuf,
+///
+/// 默认从文件读取, 也可以通过 [`LpkLoader::from_reader`] 从任意 `Read + Seek` 数据源读取.
+pub struct LpkLoader<R = File> {
+    /// LPK压缩包
+    archive: ZipArchive<R>,
     /// LPK类型
-    lpk_type: String,
+    lpk_type: LpkType,
     /// 是否加密
     encrypted: bool,
     /// 文件名转换映射
@@ -33,6 +72,23 @@ pub struct LpkLoader {
     config: LpkConfig,
     /// MLVE配置
     mlve_config: MLveConfig,
+    /// 缺少 config.json 时从已知明文恢复密钥
+    recover_keys: bool,
+    /// 是否并行解密条目
+    #[cfg(feature = "parallel")]
+    parallel: bool,
+    /// 解压事件的观察者和取消令牌
+    hooks: ExtractHooks,
+    /// 共享资源的存放方式和已经写入的共享资源
+    shared: SharedAssets,
+    /// 探测到的密钥规则, 只用于未知类型的LPK
+    detected_scheme: Option<KeyScheme>,
+    /// 已经用模型 JSON 校验过的密钥规则
+    verified_keys: HashSet<KeyScheme>,
+    /// 增量解压的记录
+    incremental: IncrementalState,
+    /// 要解压的角色和服装
+    selection: Selection,
 }
 
 impl LpkLoader {
@@ -43,97 +99,255 @@ impl LpkLoader {
 
     /// 创建新的LPK加载器
     pub fn open_with_config(lpk_path: &Path, config_path: &Path) -> Result<Self> {
+        let file = File::open(lpk_path).context(ErrorStage::Open, lpk_path.display())?;
+        LpkLoader::open_reader(file, lpk_path, config_path)
+    }
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 从已经打开的LPK文件创建加载器, Steam Workshop LPK 从 `config_path` 读取配置, 配置不存在时恢复密钥
+    fn open_reader(reader: R, lpk_path: &Path, config_path: &Path) -> Result<Self> {
+        let loader = LpkLoader::new(reader).map_err(|e| e.with_context(ErrorStage::Open, lpk_path.display()))?;
+        // 只有 Steam Workshop LPK 和可能使用 Steam 密钥的未知类型需要 config.json来解密
+        let config = match loader.needs_config() && config_path.exists() {
+            true => Some(std::fs::read(config_path).context(ErrorStage::Config, config_path.display())?),
+            false => None,
+        };
+        loader.with_config_file(config_path, config)
+    }
+
+    /// 是否需要读取 config.json
+    fn needs_config(&self) -> bool {
+        matches!(self.lpk_type, LpkType::Stm1_0 | LpkType::Unknown(_))
+    }
+
+    /// 使用 `config_path` 读取到的内容配置LPK, Steam Workshop LPK 没有内容时恢复密钥, 未知类型重新探测密钥规则
+    fn with_config_file(mut self, config_path: &Path, config: Option<Vec<u8>>) -> Result<Self> {
+        if !self.needs_config() {
+            return Ok(self);
+        }
+        match config {
+            Some(bytes) => {
+                self.config = LpkConfig::from_bytes(&bytes).context(ErrorStage::Config, config_path.display())?;
+                self.detect_key_scheme();
+            }
+            None if self.lpk_type == LpkType::Stm1_0 => {
+                warn!("{} not found, recovering keys from known plaintext", config_path.display());
+                self.recover_keys = true;
+            }
+            None => {}
+        }
+        Ok(self)
+    }
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 从任意数据源创建LPK加载器, Steam Workshop LPK 需要改用 [`LpkLoader::from_reader_with_config`]
+    pub fn from_reader(reader: R) -> Result<Self> {
+        let loader = LpkLoader::new(reader)?;
+        if loader.lpk_type == LpkType::Stm1_0 {
+            Err(LpkError::ConfigMissing)?
+        }
+        Ok(loader)
+    }
+
+    /// 从任意数据源创建LPK加载器, 并使用给定的 config.json 配置
+    pub fn from_reader_with_config(reader: R, config: LpkConfig) -> Result<Self> {
+        let mut loader = LpkLoader::new(reader)?;
+        loader.config = config;
+        loader.detect_key_scheme();
+        Ok(loader)
+    }
+
+    /// 从任意数据源创建LPK加载器, 缺少 config.json 的 Steam Workshop LPK 会从已知明文恢复密钥
+    pub fn from_reader_with_key_recovery(reader: R) -> Result<Self> {
+        let mut loader = LpkLoader::new(reader)?;
+        loader.recover_keys = loader.lpk_type == LpkType::Stm1_0;
+        Ok(loader)
+    }
+
+    /// 从任意数据源创建LPK加载器, 并使用 config.json 的原始内容
+    pub fn from_reader_with_config_bytes(reader: R, config: &[u8]) -> Result<Self> {
+        LpkLoader::from_reader_with_config(reader, LpkConfig::from_bytes(config).context(ErrorStage::Config, "config.json")?)
+    }
+
+    fn new(reader: R) -> Result<Self> {
         let mut loader = LpkLoader {
-            lpk_path: lpk_path.to_path_buf(),
-            lpk_type: String::new(),
+            archive: ZipArchive::new(reader).context(ErrorStage::Open, "")?,
+            lpk_type: LpkType::default(),
             encrypted: true,
             uncompressed: HashMap::new(),
             entrys: HashMap::new(),
             mlve_config: MLveConfig::default(),
             config: LpkConfig::default(),
+            recover_keys: false,
+            #[cfg(feature = "parallel")]
+            parallel: false,
+            hooks: ExtractHooks::default(),
+            shared: SharedAssets::default(),
+            detected_scheme: None,
+            verified_keys: HashSet::new(),
+            incremental: IncrementalState::default(),
+            selection: Selection::all(),
         };
         loader.load_lpk()?;
-        // 只有 Steam Workshop LPK 需要 config.json来解密
-        if loader.lpk_type == "STM_1_0" {
-            loader.load_config(config_path)?;
-        }
+        loader.detect_key_scheme();
         Ok(loader)
     }
 
     /// 加载LPK文件
     fn load_lpk(&mut self) -> Result<()> {
-        let file = File::open(&self.lpk_path)?;
-        let mut archive = ZipArchive::new(file)?;
-
         let mut contents = String::new();
         // 尝试读取 config.mlve 文件
-        match archive.by_name(&hashed_filename("config.mlve")) {
-            Ok(mut file) => {
-                file.read_to_string(&mut contents)?;
-            }
-            Err(_) => {}
-        };
+        let hashed = hashed_filename("config.mlve");
+        if let Ok(mut file) = self.archive.by_name(&hashed) {
+            file.read_to_string(&mut contents).context(ErrorStage::Config, &hashed)?;
+        }
         // 尝试直接读取未加密的 config.mlve
         if contents.is_empty() {
-            match archive.by_name("config.mlve") {
+            match self.archive.by_name("config.mlve") {
                 Ok(mut file) => {
-                    file.read_to_string(&mut contents)?;
+                    file.read_to_string(&mut contents).context(ErrorStage::Config, "config.mlve")?;
                 }
                 Err(_) => Err(LpkError::ConfigMissing)?,
             }
         }
-        self.mlve_config = serde_json::from_str(&contents)?;
+        self.mlve_config = MLveConfig::from_bytes(contents.as_bytes()).context(ErrorStage::Config, "config.mlve")?;
         debug!("mlve config: {:#?}", self.mlve_config);
-        self.lpk_type = self.mlve_config.r#type.to_string();
-        self.encrypted = self.mlve_config.encrypt == "encrypt";
-        Ok(())
-    }
-
-    /// 加载配置文件（用于Steam Workshop LPK）
-    fn load_config(&mut self, path: &Path) -> Result<()> {
-        let contents = std::fs::read_to_string(path)?;
-        self.config = serde_json::from_str(&contents)?;
+        self.lpk_type = self.mlve_config.r#type.clone();
+        self.encrypted = self.mlve_config.encrypt == EncryptMode::Encrypt;
         Ok(())
     }
 
     /// 解压LPK文件到指定目录
     pub fn extract(&mut self, output_dir: &Path) -> Result<()> {
-        safe_mkdir(output_dir)?;
-        match self.lpk_type.as_str() {
-            "STD2_0" => self.extract_standard(output_dir),
-            "STM_1_0" => self.extract_standard(output_dir),
-            _ => self.extract_legacy(output_dir),
+        safe_mkdir(output_dir).context(ErrorStage::Write, output_dir.display())?;
+        self.extract_to(&DirectorySink::new(output_dir))
+    }
+
+    /// 解压LPK到任意写入目标, 例如 zip 或 tar 包, 或者内存
+    pub fn extract_to(&mut self, sink: &dyn ExtractSink) -> Result<()> {
+        self.hooks.reset();
+        self.shared.reset();
+        self.emit(ExtractEvent::Opened { lpk_type: self.lpk_type.clone(), entries: self.archive.len() });
+        self.load_manifest(sink);
+        let result = match self.standard_layout() {
+            true => self.extract_standard(sink),
+            false => self.extract_legacy(sink),
+        };
+        if let Err(LpkError::Cancelled) = result {
+            info!("Extraction cancelled, removing written files");
+            self.hooks.remove_created(sink);
         }
+        result?;
+        self.save_manifest(sink)?;
+        let output = sink.location(Path::new(""));
+        sink.finish().context(ErrorStage::Write, output.display())?;
+        self.emit(ExtractEvent::Finished { output });
+        Ok(())
     }
 
-    /// 解压服装
-    fn extract_costume(&mut self, model_json: &str, dir: &Path) -> Result<()> {
+    /// 解压服装, `dir` 是相对输出根目录的角色目录
+    fn extract_costume(&mut self, model_json: &str, dir: &Path, sink: &dyn ExtractSink) -> Result<()> {
         if model_json.is_empty() {
             return Ok(());
         }
         self.check_decrypt(model_json)?;
-        self.decrypt_model_json(model_json, dir)?;
-        self.decrypt_all(dir)
+        // 写出任何文件之前先确认密钥正确
+        self.verify_key(self.key_scheme(model_json)?)?;
+        // 先确定所有条目的输出文件名再写出, 写出后不再重命名, 最后据此改写模型 JSON 中的引用
+        let files = self.costume_entries(model_json)?;
+        let files = self.skip_unchanged(dir, files, sink)?;
+        self.restore_names(model_json, &files)?;
+        // 同一个目录中已经由之前的服装写出的条目不再重复写出
+        let mut pending = Vec::with_capacity(files.len());
+        for file in files {
+            if !self.hooks.is_written(&safe_join(dir, &self.uncompressed[&file])?) {
+                pending.push(file);
+            }
+        }
+        self.decrypt_all(dir, pending, sink)?;
+        self.record_outputs(dir)?;
+        self.decrypt_model_json(model_json, dir, sink)
+    }
+
+    /// 解密数据, 模型 JSON 需要能完整解析
+    fn decrypt_data(&self, filename: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        let data = self.decrypt_raw(filename, data)?;
+        if self.is_model_json(filename) {
+            verify_model_json(filename, self.key_scheme(filename)?, &data)?;
+        }
+        Ok(data)
+    }
+
+    /// 只解密数据, 不校验结果, 用于解密条目开头
+    fn decrypt_raw(&self, filename: &str, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        let scheme = self.key_scheme(filename)?;
+        let keystream = match self.keystream(filename, scheme) {
+            Some(s) => s,
+            None if scheme == KeyScheme::Plain => return Ok(data),
+            None => match recover_keystream(&data) {
+                Some(s) => {
+                    trace!("Recovered seed {:#06x} for {}", s.seed(), filename);
+                    s
+                }
+                None => Err(LpkError::DecryptionFailed { entry: filename.to_string(), scheme })?,
+            },
+        };
+        keystream.decrypt_in_place(&mut data);
+        Ok(data)
+    }
+
+    /// 判断条目使用的密钥规则
+    fn key_scheme(&self, filename: &str) -> Result<KeyScheme> {
+        if self.is_plain_entry(filename) {
+            return Ok(KeyScheme::Plain);
+        }
+        match self.entry_scheme() {
+            Some(scheme) => Ok(scheme),
+            None => Err(DecodeError {
+                format: self.lpk_type.to_string(),
+                path: filename.to_string(),
+                stage: ErrorStage::Decrypt,
+                message: "unimplement".to_string(),
+                source: None,
+            }),
+        }
     }
 
-    /// 解密数据
-    fn decrypt_data(&self, filename: &str, data: &[u8]) -> Result<Vec<u8>> {
-        match self.lpk_type.as_ref() {
+    /// 加密条目使用的密钥规则
+    fn entry_scheme(&self) -> Option<KeyScheme> {
+        match self.lpk_type {
             // 标准 LPK 直接使用文件名作为密钥
-            "STD_1_0" | "STD2_0" => {
+            LpkType::Std2_0 => Some(KeyScheme::Standard),
+            LpkType::Stm1_0 if self.mlve_config.encrypt == EncryptMode::Disabled => Some(KeyScheme::Zero),
+            // 缺少 config.json 时只能从已知明文恢复
+            LpkType::Stm1_0 if self.recover_keys => Some(KeyScheme::Recovered),
+            // Steam Workshop LPK 需要读取 config.json 作为密钥
+            LpkType::Stm1_0 => Some(KeyScheme::Steam),
+            LpkType::Std1_0 => Some(KeyScheme::Standard),
+            // 未知类型使用探测到的规则
+            _ => self.detected_scheme,
+        }
+    }
+
+    /// 根据文件名生成解密密钥流, 明文条目和需要恢复密钥的条目没有固定的密钥流
+    fn keystream(&self, filename: &str, scheme: KeyScheme) -> Option<Keystream> {
+        let key = match scheme {
+            KeyScheme::Plain | KeyScheme::Recovered => return None,
+            KeyScheme::Zero => 0,
+            KeyScheme::Standard => {
                 let key = format!("{}{filename}", self.mlve_config.id);
                 trace!("Standalone Key: {}", key);
-                Ok(decrypt(make_key(&key), data))
+                make_key(&key)
             }
-            "STM_1_0" if self.mlve_config.encrypt == "false" => Ok(decrypt(0, data)),
-            // Steam Workshop LPK 需要读取 config.json 作为密钥
-            "STM_1_0" => {
+            KeyScheme::Steam => {
                 let key = format!("{}{}{filename}{}", self.mlve_config.id, self.config.file_id, self.config.meta_data);
                 trace!("Steam Key: {}", key);
-                Ok(decrypt(make_key(&key), data))
+                make_key(&key)
             }
-            _ => Err(DecodeError { format: self.lpk_type.to_string(), message: "unimplement".to_string() }),
-        }
+        };
+        Some(Keystream::new(key))
     }
 
     /// 检查文件是否需要解密，并处理加密文件
@@ -145,21 +359,17 @@ impl LpkLoader {
 
         // 如果是加密文件名，需要先解密
         if is_encrypted_file(model_json) {
-            let file = File::open(&self.lpk_path)?;
-            let mut archive = ZipArchive::new(file)?;
-
-            // 尝试直接打开文件
-            let file = match archive.by_name(model_json) {
-                Ok(_) => {
+            // 尝试直接查找文件
+            match self.archive.index_for_name(model_json) {
+                Some(_) => {
                     // 文件存在，不需要额外处理
                     Ok(())
                 }
-                Err(_) => {
+                None => {
                     // 文件不存在，可能需要进一步处理
-                    Err(LpkError::DecryptionFailed(format!("File not found: {}", model_json)))
+                    Err(LpkError::ModelMissing(model_json.to_string()))
                 }
-            };
-            file
+            }
         }
         else {
             // 非加密文件名，不需要处理
@@ -168,47 +378,217 @@ impl LpkLoader {
     }
 
     /// 解压模型 JSON 文件
-    fn decrypt_model_json(&mut self, model_json: &str, dir: &Path) -> Result<()> {
-        let file = File::open(&self.lpk_path)?;
-        let mut archive = ZipArchive::new(file)?;
-        let mut file = archive.by_name(model_json)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+    fn decrypt_model_json(&mut self, model_json: &str, dir: &Path, sink: &dyn ExtractSink) -> Result<()> {
+        let buffer = read_raw(&mut self.archive, model_json, &self.hooks)?;
         for character in self.mlve_config.list.as_slice() {
             debug!("Export character `{}`", character.character);
-            for costume in character.costume.as_slice() {
+            for costume in character.costume.iter().filter(|c| c.path == model_json) {
                 debug!("Export costume `{}({})`", costume.name, character.character);
-                let decrypted_data = self.decrypt_data(&costume.path, &buffer)?;
-                let json_str = String::from_utf8(decrypted_data).unwrap();
-                let output = format!("{}-{}.model3.json", character.character, costume.name);
-                let path = dir.join(output);
-                let mut file = File::create(&path)?;
-                file.write_all(json_str.as_bytes())?;
-                debug!("Exported {}", path.canonicalize()?.display());
+                let decrypted_data = self.decrypt_data(&costume.path, buffer.clone())?;
+                let json_str = match String::from_utf8(decrypted_data) {
+                    Ok(s) if self.shared.layout == AssetLayout::SharedPaths => {
+                        self.rewrite_shared_paths(self.rewrite_references(s), dir)?
+                    }
+                    Ok(s) => self.rewrite_references(s),
+                    Err(_) => {
+                        Err(LpkError::DecryptionFailed { entry: costume.path.clone(), scheme: self.key_scheme(model_json)? })?
+                    }
+                };
+                let path = safe_join(dir, &model_json_name(&character.character, &costume.name))?;
+                if self.hooks.is_written(&path) {
+                    continue;
+                }
+                self.hooks
+                    .write(sink, &path, json_str.as_bytes())
+                    .context(ErrorStage::Write, sink.location(&path).display())?;
+                debug!("Exported {}", path.display());
             }
         }
         Ok(())
     }
 
-    fn decrypt_all(&self, output: &Path) -> Result<()> {
-        let file = File::open(&self.lpk_path)?;
-        let mut archive = ZipArchive::new(file)?;
-        let all_files = archive.file_names().map(|s| s.to_string()).collect::<Vec<_>>();
-        for file in all_files {
-            let mut encrypted_file = archive.by_name(&file)?;
-            let mut buffer = Vec::new();
-            encrypted_file.read_to_end(&mut buffer)?;
-            let decrypted_data = self.decrypt_data(&file, &buffer)?;
-            let output = output.join(&file);
-            match std::fs::write(&output, decrypted_data) {
-                Ok(_) => {
-                    debug!("Exported {}", output.canonicalize()?.display());
-                }
+    /// 替换加密文件名为恢复后的文件名
+    fn rewrite_references(&self, mut json: String) -> String {
+        for (k, v) in &self.uncompressed {
+            json = json.replace(k, v);
+        }
+        json
+    }
+
+    fn decrypt_all(&mut self, output: &Path, all_files: Vec<String>, sink: &dyn ExtractSink) -> Result<()> {
+        // 恢复密钥需要先读取密文, 只能顺序处理
+        #[cfg(feature = "parallel")]
+        if self.parallel && !self.recover_keys {
+            return self.decrypt_all_parallel(output, all_files, sink);
+        }
+        let total = all_files.len();
+        for (done, file) in all_files.into_iter().enumerate() {
+            let scheme = self.key_scheme(&file)?;
+            if let Some(path) = self.stream_output(output, &file, scheme)? {
+                let (keystream, hooks) = (self.keystream(&file, scheme), &self.hooks);
+                stream_entry(&mut self.archive, hooks, &file, keystream, sink, |head, bytes| {
+                    warn_unrecognized(hooks, &file, scheme, head);
+                    hooks.emit(ExtractEvent::EntryDecrypted { name: file.clone(), bytes, done: done + 1, total });
+                    Ok(path)
+                })?;
+                continue;
+            }
+            let buffer = read_raw(&mut self.archive, &file, &self.hooks)?;
+            let decrypted_data = self.decrypt_data(&file, buffer)?;
+            warn_unrecognized(&self.hooks, &file, scheme, &decrypted_data);
+            let bytes = decrypted_data.len() as u64;
+            self.emit(ExtractEvent::EntryDecrypted { name: file.clone(), bytes, done: done + 1, total });
+            let name = output_name(&self.uncompressed, &file, FileType::sniff(&decrypted_data));
+            let output = safe_join(output, &name)?;
+            self.uncompressed.insert(file, name);
+            match self.shared.write_asset(&self.hooks, sink, &output, &decrypted_data) {
+                Ok(_) => debug!("Exported {}", output.display()),
                 Err(err) => {
+                    let output = sink.location(&output);
                     error!("Failed to write file {}: {}", output.display(), err);
+                    self.emit(ExtractEvent::Warning { message: format!("Failed to write file {}: {}", output.display(), err) });
                 }
             }
         }
         Ok(())
     }
 }
+
+/// 分块读取条目时每块的大小, 块之间检查取消令牌
+const READ_CHUNK: u64 = 64 * 1024;
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 可以分块解密的条目的输出路径
+    ///
+    /// 较小的条目, 模型 JSON, 需要从密文恢复密钥的条目和写入前需要计算摘要的共享资源仍然完整读取.
+    pub(crate) fn stream_output(&mut self, dir: &Path, file: &str, scheme: KeyScheme) -> Result<Option<PathBuf>> {
+        let Some(name) = self.uncompressed.get(file)
+        else {
+            return Ok(None);
+        };
+        let output = safe_join(dir, name)?;
+        if scheme == KeyScheme::Recovered
+            || self.is_model_json(file)
+            || (self.shared.layout != AssetLayout::Copy && is_shareable(&output))
+        {
+            return Ok(None);
+        }
+        match self.archive.by_name(file).context(ErrorStage::Decrypt, file)?.size() >= STREAM_THRESHOLD {
+            true => Ok(Some(output)),
+            false => Ok(None),
+        }
+    }
+}
+
+/// 不小于这个大小的条目分块解密后直接写入目标, 不在内存中保留完整内容
+const STREAM_THRESHOLD: u64 = 1024 * 1024;
+
+/// 分块解密时先解密并校验的开头长度
+const STREAM_HEAD: u64 = 4096;
+
+/// 读取条目未解密的原始内容
+fn read_raw<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str, hooks: &ExtractHooks) -> Result<Vec<u8>> {
+    hooks.check()?;
+    let mut file = archive.by_name(name).context(ErrorStage::Decrypt, name)?;
+    let mut buffer = Vec::with_capacity(file.size().min(PREALLOCATE_LIMIT) as usize);
+    while (&mut file).take(READ_CHUNK).read_to_end(&mut buffer).context(ErrorStage::Decrypt, name)? > 0 {
+        hooks.check()?;
+    }
+    Ok(buffer)
+}
+
+/// 分块解密条目并写入目标, 内存中只保留一块
+///
+/// 先解密开头交给 `output` 检查并给出输出路径, 参数是解密后的开头和条目大小, 返回错误时不写出.
+/// 写入失败时发出警告, 读取压缩包失败时返回错误.
+fn stream_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    hooks: &ExtractHooks,
+    name: &str,
+    keystream: Option<Keystream>,
+    sink: &dyn ExtractSink,
+    output: impl FnOnce(&[u8], u64) -> Result<PathBuf>,
+) -> Result<()> {
+    hooks.check()?;
+    let mut file = archive.by_name(name).context(ErrorStage::Decrypt, name)?;
+    let size = file.size();
+    let mut head = Vec::with_capacity(STREAM_HEAD as usize);
+    (&mut file).take(STREAM_HEAD).read_to_end(&mut head).context(ErrorStage::Decrypt, name)?;
+    if let Some(keystream) = &keystream {
+        keystream.decrypt_at(0, &mut head);
+    }
+    let path = output(&head, size)?;
+    let offset = head.len() as u64;
+    let mut reader = Cursor::new(head).chain(DecryptReader { inner: file, keystream, offset, hooks, failed: None });
+    match hooks.write_from(sink, &path, size, &mut reader) {
+        Ok(_) => debug!("Exported {}", path.display()),
+        Err(err) => {
+            hooks.check()?;
+            if let Some(e) = reader.get_mut().1.failed.take() {
+                Err(e).context(ErrorStage::Decrypt, name)?;
+            }
+            let path = sink.location(&path);
+            error!("Failed to write file {}: {}", path.display(), err);
+            hooks.emit(ExtractEvent::Warning { message: format!("Failed to write file {}: {}", path.display(), err) });
+        }
+    }
+    Ok(())
+}
+
+/// 边读取边解密条目, 每块之间检查取消令牌
+struct DecryptReader<'a, R: Read> {
+    inner: R,
+    keystream: Option<Keystream>,
+    offset: u64,
+    hooks: &'a ExtractHooks,
+    /// 读取压缩包时的错误, 与写入错误区分
+    failed: Option<std::io::Error>,
+}
+
+impl<R: Read> Read for DecryptReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // 不能使用 Interrupted, `io::copy` 会重试
+        if self.hooks.check().is_err() {
+            return Err(std::io::Error::other("cancelled"));
+        }
+        let n = match self.inner.read(buf) {
+            Ok(n) => n,
+            Err(e) => {
+                let error = std::io::Error::new(e.kind(), e.to_string());
+                self.failed = Some(e);
+                return Err(error);
+            }
+        };
+        if let Some(keystream) = &self.keystream {
+            keystream.decrypt_at(self.offset, &mut buf[..n]);
+        }
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+/// 服装模型 JSON 的输出文件名
+fn model_json_name(character: &str, costume: &str) -> String {
+    sanitize_file_name(&format!("{character}-{costume}.model3.json"), "model.model3.json")
+}
+
+/// 角色的输出目录名
+fn character_dir_name(character: &str) -> String {
+    sanitize_file_name(character, "character")
+}
+
+/// 条目的输出文件名, 优先使用已经恢复的文件名, 否则根据识别出的文件类型为加密文件名补上正确的扩展名
+fn output_name(restored: &HashMap<String, String>, file: &str, file_type: FileType) -> String {
+    match restored.get(file) {
+        Some(name) => name.clone(),
+        None if needs_extension(Path::new(file)) => {
+            Path::new(file).with_extension(file_type.extension()).to_string_lossy().to_string()
+        }
+        None => file.to_string(),
+    }
+}
+
+/// 没有扩展名或者只有 .bin 扩展名的文件需要根据内容补全扩展名, 其他扩展名保持不变
+fn needs_extension(path: &Path) -> bool {
+    matches!(path.extension().and_then(|s| s.to_str()).unwrap_or(""), "" | "bin" | "bin3")
+}
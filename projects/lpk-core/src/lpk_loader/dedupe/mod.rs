@@ -0,0 +1,147 @@
+use super::*;
+use crate::sink::relative_path;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// 共享资源的目录, 位于输出根目录
+pub const SHARED_ASSETS_DIR: &str = "_shared";
+
+/// 多个角色和服装共用的资源的存放方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AssetLayout {
+    /// 每个角色目录各保存一份
+    #[default]
+    Copy,
+    /// 在共享目录保存一份, 角色目录中使用硬链接
+    Hardlink,
+    /// 在共享目录保存一份, 角色目录中使用相对路径的符号链接
+    Symlink,
+    /// 在共享目录保存一份, 改写模型 JSON 中的引用指向共享目录
+    SharedPaths,
+}
+
+/// 共享资源的存放方式和本次解压写入的共享资源, 并行解密时由工作线程共同使用
+#[derive(Default)]
+pub(crate) struct SharedAssets {
+    /// 共享资源的存放方式
+    pub(crate) layout: AssetLayout,
+    /// 本次解压写入共享目录的资源, 角色目录中的路径 -> 共享文件
+    assets: Mutex<HashMap<PathBuf, PathBuf>>,
+    /// 写入目标不支持链接, 已经发出过警告
+    links_unsupported: AtomicBool,
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 设置共享资源的存放方式, 默认每个角色目录各保存一份
+    ///
+    /// 资源以内容的 MD5 命名保存在 [`SHARED_ASSETS_DIR`] 中, 写入之前就确定共享文件的位置, 内容相同的资源只写入一次.
+    /// 写入目标不支持链接时角色目录中仍然各保存一份, 目前只对标准格式生效.
+    pub fn set_asset_layout(&mut self, layout: AssetLayout) {
+        self.shared.layout = layout;
+    }
+
+    /// 改写模型 JSON 中指向共享资源的引用, `dir` 是相对输出根目录的角色目录
+    pub(crate) fn rewrite_shared_paths(&self, json: String, dir: &Path) -> Result<String> {
+        let shared = self.shared.assets.lock().map(|assets| assets.clone()).unwrap_or_default();
+        shared_model_json(json, dir, &|path| shared.get(path).cloned())
+    }
+}
+
+impl SharedAssets {
+    /// 开始新一次解压
+    pub(crate) fn reset(&self) {
+        if let Ok(mut assets) = self.assets.lock() {
+            assets.clear();
+        }
+        self.links_unsupported.store(false, Ordering::Relaxed);
+    }
+
+    /// 写入资源, 非 [`AssetLayout::Copy`] 时先写入以 MD5 命名的共享文件, 再在角色目录中链接到它
+    pub(crate) fn write_asset(
+        &self,
+        hooks: &ExtractHooks,
+        sink: &dyn ExtractSink,
+        path: &Path,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        if self.layout == AssetLayout::Copy || !is_shareable(path) {
+            return hooks.write(sink, path, data);
+        }
+        let shared = shared_asset_path(path, data);
+        // 持有锁直到共享文件写入完成, 避免并行写入时其他线程链接到还没写入的文件
+        let mut assets = self.assets.lock().map_err(|_| std::io::Error::other("shared assets poisoned"))?;
+        if !hooks.is_written(&shared) {
+            hooks.write(sink, &shared, data)?;
+            trace!("Shared {} as {}", path.display(), shared.display());
+        }
+        if self.layout != AssetLayout::SharedPaths {
+            let existed = sink.exists(path);
+            match sink.link(&shared, path, self.layout == AssetLayout::Symlink) {
+                Ok(_) => hooks.record(sink, path, existed, data),
+                // 不支持链接时保存为普通文件
+                Err(e) if e.kind() == ErrorKind::Unsupported => {
+                    if !self.links_unsupported.swap(true, Ordering::Relaxed) {
+                        let message = format!("{:?} is not supported by the output, shared assets are copied", self.layout);
+                        warn!("{}", message);
+                        hooks.emit(ExtractEvent::Warning { message });
+                    }
+                    hooks.write(sink, path, data)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        assets.insert(path.to_path_buf(), shared);
+        Ok(())
+    }
+}
+
+/// 资源的共享文件, 以内容的 MD5 命名并保留原来的扩展名
+pub(crate) fn shared_asset_path(path: &Path, data: &[u8]) -> PathBuf {
+    let extension = path.extension().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    Path::new(SHARED_ASSETS_DIR).join(format!("{:x}.{extension}", md5::compute(data)))
+}
+
+/// 将模型 JSON 中指向已共享资源的引用改为指向共享目录的相对路径, 没有引用被改写时保持原样
+pub(crate) fn shared_model_json(json: String, dir: &Path, shared: &dyn Fn(&Path) -> Option<PathBuf>) -> Result<String> {
+    let Ok(mut value) = serde_json::from_str::<Value>(&json)
+    else {
+        return Ok(json);
+    };
+    let mut changed = false;
+    rewrite_strings(&mut value, &mut |s| {
+        let target = shared(&safe_join(dir, s).ok()?)?;
+        changed = true;
+        Some(relative_path(dir, &target).to_string_lossy().replace('\\', "/"))
+    });
+    match changed {
+        true => Ok(serde_json::to_string_pretty(&value)?),
+        false => Ok(json),
+    }
+}
+
+/// 替换 JSON 中所有能被 `f` 映射的字符串
+fn rewrite_strings(value: &mut Value, f: &mut impl FnMut(&str) -> Option<String>) {
+    match value {
+        Value::String(s) => {
+            if let Some(new) = f(s) {
+                *s = new;
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(|v| rewrite_strings(v, f)),
+        Value::Object(object) => object.values_mut().for_each(|v| rewrite_strings(v, f)),
+        _ => {}
+    }
+}
+
+/// 可以共享的文件, 模型 JSON 和映射在每个目录中各自保留
+pub(crate) fn is_shareable(path: &Path) -> bool {
+    !path.to_string_lossy().ends_with(".model3.json") && !path.ends_with("mapping.json") && !path.starts_with(SHARED_ASSETS_DIR)
+}
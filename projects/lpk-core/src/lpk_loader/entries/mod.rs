@@ -0,0 +1,192 @@
+use super::{info::is_text_name, restore::model_references, *};
+use crate::{
+    file_type::SNIFF_LEN,
+    recovery::{recover_head_keystream, HEADER_LEN},
+};
+use serde_json::Value;
+use std::io::Cursor;
+
+/// LPK中的逻辑条目
+#[derive(Clone, Debug)]
+pub struct LpkEntry {
+    /// 压缩包中的条目名, 加密包中通常是哈希后的文件名
+    pub name: String,
+    /// 可以推断时的原始文件名
+    pub original_name: Option<String>,
+    /// 所属角色
+    pub character: Option<String>,
+    /// 所属服装
+    pub costume: Option<String>,
+    /// 解密后的大小
+    pub size: u64,
+    /// 条目用途
+    pub kind: EntryKind,
+    /// 根据解密后内容识别的文件类型
+    pub file_type: FileType,
+}
+
+/// 条目在LPK中的用途
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    /// `config.mlve` 配置文件
+    Config,
+    /// 服装的模型 JSON
+    Model,
+    /// 角色头像
+    Avatar,
+    /// 其他资源 (纹理, 动作, 物理等)
+    Asset,
+}
+
+/// 已解密条目的读取器
+pub struct EntryReader {
+    inner: Cursor<Vec<u8>>,
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 获取 MLVE 配置
+    pub fn mlve_config(&self) -> &MLveConfig {
+        &self.mlve_config
+    }
+
+    /// 列出LPK中的所有条目
+    ///
+    /// 只解密每个条目的开头来识别文件类型, 无法解密的条目类型为 [`FileType::Unknown`].
+    pub fn entries(&mut self) -> Result<Vec<LpkEntry>> {
+        let owners = self.asset_owners();
+        let mut entries = Vec::with_capacity(self.archive.len());
+        for i in 0..self.archive.len() {
+            let (name, size) = {
+                let file = self.archive.by_index_raw(i).context(ErrorStage::Decrypt, format!("#{i}"))?;
+                if file.is_dir() {
+                    continue;
+                }
+                (file.name().to_string(), file.size())
+            };
+            let file_type = self.sniff_entry(&name);
+            let mut entry = self.describe_entry(name, size, file_type);
+            if let (EntryKind::Asset, Some((character, costume, readable))) = (entry.kind, owners.get(&entry.name)) {
+                entry.character = Some(character.clone());
+                entry.costume = Some(costume.clone());
+                entry.original_name = Some(readable.replace("{ext}", file_type.extension()));
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// 模型 JSON 引用的资源 -> `(角色, 服装, 可读文件名)`, 被多个服装引用时取第一个, 无法解密的模型 JSON 会被跳过
+    fn asset_owners(&mut self) -> HashMap<String, (String, String, String)> {
+        let mut owners = HashMap::new();
+        let costumes = self.mlve_config.list.iter().flat_map(|chara| chara.costume.iter().map(move |c| (chara, c)));
+        let costumes = costumes
+            .filter(|(_, costume)| !costume.path.is_empty())
+            .map(|(chara, costume)| (chara.character.clone(), costume.name.clone(), costume.path.clone()))
+            .collect::<Vec<_>>();
+        for (character, costume, model_json) in costumes {
+            let json = match self.read_entry(&model_json).map(|data| serde_json::from_slice::<Value>(&data)) {
+                Ok(Ok(o)) => o,
+                Ok(Err(e)) => {
+                    warn!("Failed to parse model json {}: {}", model_json, e);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to read model json {}: {}", model_json, e.english());
+                    continue;
+                }
+            };
+            for (reference, readable) in model_references(&json) {
+                if let Some(name) = self.resolve_reference(&model_json, &reference) {
+                    owners.entry(name).or_insert_with(|| (character.clone(), costume.clone(), readable));
+                }
+            }
+        }
+        owners
+    }
+
+    /// 以解密后的内容打开条目
+    pub fn open_entry(&mut self, name: &str) -> Result<EntryReader> {
+        Ok(EntryReader { inner: Cursor::new(self.read_entry(name)?) })
+    }
+
+    /// 读取条目并返回解密后的内容, 密钥错误时返回 [`LpkError::DecryptionFailed`]
+    pub fn read_entry(&mut self, name: &str) -> Result<Vec<u8>> {
+        self.verify_key(self.key_scheme(name)?)?;
+        let buffer = read_raw(&mut self.archive, name, &self.hooks)?;
+        self.decrypt_data(name, buffer)
+    }
+
+    /// 只解密条目开头来识别文件类型, 无法读取或解密时为 [`FileType::Unknown`]
+    pub(crate) fn sniff_entry(&mut self, name: &str) -> FileType {
+        match self.sniff_head(name) {
+            Ok(o) => o,
+            Err(e) => {
+                debug!("Unable to sniff {}: {}", name, e.english());
+                FileType::Unknown
+            }
+        }
+    }
+
+    fn sniff_head(&mut self, name: &str) -> Result<FileType> {
+        let scheme = self.key_scheme(name)?;
+        // 恢复密钥需要更长的开头来校验文件头结构
+        let length = match scheme {
+            KeyScheme::Recovered => HEADER_LEN,
+            _ => SNIFF_LEN,
+        };
+        let mut head = Vec::with_capacity(length);
+        let file = self.archive.by_name(name).context(ErrorStage::Decrypt, name)?;
+        file.take(length as u64).read_to_end(&mut head).context(ErrorStage::Decrypt, name)?;
+        match self.keystream(name, scheme) {
+            Some(keystream) => keystream.decrypt_in_place(&mut head),
+            None if scheme == KeyScheme::Plain => {}
+            None => match recover_head_keystream(&head) {
+                Some(keystream) => keystream.decrypt_in_place(&mut head),
+                None => Err(LpkError::DecryptionFailed { entry: name.to_string(), scheme })?,
+            },
+        }
+        Ok(FileType::sniff(&head))
+    }
+
+    /// 根据 config.mlve 推断条目的角色, 服装和用途
+    fn describe_entry(&self, name: String, size: u64, file_type: FileType) -> LpkEntry {
+        let mut entry =
+            LpkEntry { name, original_name: None, character: None, costume: None, size, kind: EntryKind::Asset, file_type };
+        if entry.name == "config.mlve" || entry.name == hashed_filename("config.mlve") {
+            entry.original_name = Some("config.mlve".to_string());
+            entry.kind = EntryKind::Config;
+            return entry;
+        }
+        for chara in self.mlve_config.list.as_slice() {
+            if chara.avatar == entry.name {
+                entry.character = Some(chara.character.clone());
+                entry.kind = EntryKind::Avatar;
+            }
+            if let Some(costume) = chara.costume.iter().find(|c| c.path == entry.name) {
+                entry.original_name = Some(format!("{}-{}.model3.json", chara.character, costume.name));
+                entry.character = Some(chara.character.clone());
+                entry.costume = Some(costume.name.clone());
+                entry.kind = EntryKind::Model;
+            }
+        }
+        entry
+    }
+
+    /// 判断条目是否以明文存储
+    pub(crate) fn is_plain_entry(&self, name: &str) -> bool {
+        if name == "config.mlve" || name == hashed_filename("config.mlve") {
+            return true;
+        }
+        if self.lpk_type.is_standard() {
+            return false;
+        }
+        // 旧版格式的文本文件不加密, 探测到密钥规则时不再参考 encrypt 字段
+        (!self.encrypted && self.detected_scheme.is_none()) || is_text_name(name)
+    }
+}
@@ -0,0 +1,240 @@
+use super::*;
+use crate::{CancellationToken, ExtractSink};
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// 解压过程中产生的事件
+#[derive(Clone, Debug)]
+pub enum ExtractEvent {
+    /// 开始解压LPK
+    Opened {
+        /// LPK类型
+        lpk_type: LpkType,
+        /// 压缩包中的条目数量
+        entries: usize,
+    },
+    /// 开始解压角色的服装
+    CharacterStarted {
+        /// 角色名称
+        character: String,
+        /// 服装名称
+        costume: String,
+    },
+    /// 条目已解密
+    EntryDecrypted {
+        /// 压缩包中的条目名
+        name: String,
+        /// 解密后的大小
+        bytes: u64,
+        /// 本轮已经解密的条目数量
+        done: usize,
+        /// 本轮需要解密的条目总数
+        total: usize,
+    },
+    /// 文件已写入
+    FileWritten {
+        /// 输出路径
+        path: PathBuf,
+        /// 写入的大小
+        bytes: u64,
+    },
+    /// 增量解压时来源和输出都没有变化, 跳过的文件
+    Skipped {
+        /// 输出路径
+        path: PathBuf,
+        /// 文件大小
+        bytes: u64,
+    },
+    /// 不影响继续解压的问题
+    Warning {
+        /// 英文描述
+        message: String,
+    },
+    /// 解压完成
+    Finished {
+        /// 输出目录
+        output: PathBuf,
+    },
+}
+
+/// 解压事件的观察者, 开启并行解密时会在工作线程中被调用
+pub trait ExtractObserver: Send + Sync {
+    /// 接收一个解压事件
+    fn on_event(&self, event: &ExtractEvent);
+}
+
+impl<F> ExtractObserver for F
+where
+    F: Fn(&ExtractEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &ExtractEvent) {
+        self(event)
+    }
+}
+
+/// 接收一个文件写入内容的函数, 依次收到每一块内容, 写入成功后收到 `None`
+pub(crate) type WriteTap = Box<dyn FnMut(Option<&[u8]>)>;
+
+/// 写入文件时的回调, 开始写入一个文件时调用, 返回接收这个文件内容的函数
+pub(crate) type WriteCallback = Arc<dyn Fn(&Path) -> WriteTap + Send + Sync>;
+
+/// 解压过程中共享的观察者, 取消令牌和本次写入的文件, 工作线程只借用这一部分
+#[derive(Default)]
+pub(crate) struct ExtractHooks {
+    /// 解压事件的观察者
+    observer: Option<Arc<dyn ExtractObserver>>,
+    /// 取消令牌
+    cancel: CancellationToken,
+    /// 本次解压新建的文件, 相对输出根目录, 取消时删除, 覆盖的已有文件不在其中
+    created: Mutex<Vec<PathBuf>>,
+    /// 本次解压写入的所有文件, 同一个文件只写入一次, 归档类的目标不能覆盖已经写出的文件
+    written: Mutex<HashSet<PathBuf>>,
+    /// 写入文件时的回调
+    on_write: Option<WriteCallback>,
+}
+
+impl ExtractHooks {
+    /// 通知观察者
+    pub(crate) fn emit(&self, event: ExtractEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event);
+        }
+    }
+
+    /// 写入文件, 记录下来并通知观察者
+    pub(crate) fn write(&self, sink: &dyn ExtractSink, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let existed = sink.exists(path);
+        sink.write(path, data)?;
+        self.record(sink, path, existed, data);
+        Ok(())
+    }
+
+    /// 从 `reader` 写入 `size` 字节的文件, 记录下来并通知观察者, 失败时删除写了一半的新文件
+    pub(crate) fn write_from(
+        &self,
+        sink: &dyn ExtractSink,
+        path: &Path,
+        size: u64,
+        reader: &mut dyn Read,
+    ) -> std::io::Result<()> {
+        let existed = sink.exists(path);
+        let mut reader = TapReader { inner: reader, tap: self.tap(path), size: 0 };
+        if let Err(e) = sink.write_from(path, size, &mut reader) {
+            if !existed && sink.exists(path) {
+                let _ = sink.remove(path);
+            }
+            return Err(e);
+        }
+        if let Some(tap) = &mut reader.tap {
+            tap(None);
+        }
+        self.record_size(sink, path, existed, reader.size);
+        Ok(())
+    }
+
+    /// 记录已经写入或者链接的文件并通知观察者, `existed` 表示写入之前文件是否已经存在
+    pub(crate) fn record(&self, sink: &dyn ExtractSink, path: &Path, existed: bool, data: &[u8]) {
+        if let Some(mut tap) = self.tap(path) {
+            tap(Some(data));
+            tap(None);
+        }
+        self.record_size(sink, path, existed, data.len() as u64)
+    }
+
+    fn record_size(&self, sink: &dyn ExtractSink, path: &Path, existed: bool, size: u64) {
+        if let Ok(mut created) = self.created.lock() {
+            if !existed && !created.iter().any(|p| p == path) {
+                created.push(path.to_path_buf());
+            }
+        }
+        if let Ok(mut written) = self.written.lock() {
+            written.insert(path.to_path_buf());
+        }
+        self.emit(ExtractEvent::FileWritten { path: sink.location(path), bytes: size });
+    }
+
+    /// 设置写入文件时的回调, 替换之前设置的回调
+    pub(crate) fn set_write_callback(&mut self, callback: Option<WriteCallback>) {
+        self.on_write = callback;
+    }
+
+    /// 开始写入文件时调用回调
+    fn tap(&self, path: &Path) -> Option<WriteTap> {
+        self.on_write.as_ref().map(|on_write| on_write(path))
+    }
+
+    /// 已经请求取消时返回 [`LpkError::Cancelled`]
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.cancel.is_cancelled() {
+            true => Err(LpkError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// 本次解压是否已经写入了这个文件
+    pub(crate) fn is_written(&self, path: &Path) -> bool {
+        self.written.lock().map(|written| written.contains(path)).unwrap_or(false)
+    }
+
+    /// 开始新一次解压
+    pub(crate) fn reset(&self) {
+        if let Ok(mut created) = self.created.lock() {
+            created.clear();
+        }
+        if let Ok(mut written) = self.written.lock() {
+            written.clear();
+        }
+    }
+
+    /// 删除本次解压新建的文件
+    pub(crate) fn remove_created(&self, sink: &dyn ExtractSink) {
+        let Ok(mut created) = self.created.lock()
+        else {
+            return;
+        };
+        for path in created.drain(..) {
+            match sink.remove(&path) {
+                Ok(_) => trace!("Removed {}", path.display()),
+                Err(err) => warn!("Failed to remove {}: {}", path.display(), err),
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 设置解压事件的观察者, 替换之前设置的观察者
+    pub fn set_observer(&mut self, observer: impl ExtractObserver + 'static) {
+        self.hooks.observer = Some(Arc::new(observer));
+    }
+
+    /// 设置取消令牌, 取消后解压返回 [`LpkError::Cancelled`] 并删除本次新建的文件, 覆盖的已有文件会保留
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.hooks.cancel = token;
+    }
+
+    /// 通知观察者
+    pub(crate) fn emit(&self, event: ExtractEvent) {
+        self.hooks.emit(event)
+    }
+}
+
+/// 统计读取的字节数, 同时把内容交给写入回调
+struct TapReader<'a> {
+    inner: &'a mut dyn Read,
+    tap: Option<WriteTap>,
+    size: u64,
+}
+
+impl Read for TapReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(tap) = &mut self.tap {
+            tap(Some(&buf[..n]));
+        }
+        self.size += n as u64;
+        Ok(n)
+    }
+}
@@ -1,57 +1,69 @@
 use super::*;
 
-impl LpkLoader {
+impl<R: Read + Seek> LpkLoader<R> {
     /// 解压标准格式的LPK文件（STD2_0或STM_1_0）
-    pub(crate) fn extract_standard<P: AsRef<Path>>(&mut self, output_dir: P) -> Result<()> {
-        let output_dir = output_dir.as_ref();
-
+    pub(crate) fn extract_standard(&mut self, sink: &dyn ExtractSink) -> Result<()> {
         // 先收集所有需要处理的角色和服装信息
         let mut extraction_tasks = Vec::new();
 
-        for chara in self.mlve_config.list.as_slice() {
+        for (chara, costume) in self.selected_costumes()? {
             // 获取角色名称
-            let chara_name = match chara.character.as_str() {
-                "" => "character",
-                name => name,
-            };
+            let chara_name = character_dir_name(&chara.character);
 
-            let subdir = output_dir.join(&chara_name);
-            safe_mkdir(&subdir)?;
+            let subdir = safe_join(Path::new(""), &chara_name)?;
 
             // 收集服装信息
-            for (i, costume) in chara.costume.iter().enumerate() {
-                let path = costume.path.as_str();
-                if !path.is_empty() {
-                    extraction_tasks.push((path.to_string(), subdir.clone(), format!("{}_costume_{}", chara_name, i)));
-                }
+            if !costume.path.is_empty() {
+                let event =
+                    ExtractEvent::CharacterStarted { character: chara.character.clone(), costume: costume.name.clone() };
+                extraction_tasks.push((costume.path.clone(), subdir, format!("{}/{}", chara_name, costume.name), event));
             }
         }
 
         // 处理所有服装
-        for (path, subdir, costume_name) in extraction_tasks {
+        let mut costume_dirs = Vec::new();
+        for (path, subdir, costume_name, event) in extraction_tasks {
             info!("extracting {}", costume_name);
-            self.extract_costume(&path, &subdir)?;
+            self.hooks.check()?;
+            self.emit(event);
+            self.extract_costume(&path, &subdir, sink)?;
+            if !costume_dirs.contains(&subdir) {
+                costume_dirs.push(subdir);
+            }
+        }
+        // 所有服装的文件名都确定后, 每个角色目录只写出一次映射
+        for dir in costume_dirs {
+            self.hooks.check()?;
+            self.write_mapping(&dir, sink)?;
         }
 
-        // 处理所有条目
-        for chara in self.mlve_config.list.as_slice() {
-            let chara_name = match chara.character.as_str() {
-                "" => "character",
-                name => name,
-            };
-
-            let subdir = output_dir.join(chara_name);
+        // 处理选中角色的所有条目
+        let selection = &self.selection;
+        let characters = self
+            .mlve_config
+            .list
+            .iter()
+            .filter(|chara| selection.is_all() || chara.costume.iter().any(|costume| selection.selects(chara, costume)));
+        let mut dirs = Vec::new();
+        for chara in characters {
+            let subdir = safe_join(Path::new(""), &character_dir_name(&chara.character))?;
+            if dirs.contains(&subdir) {
+                continue;
+            }
+            dirs.push(subdir.clone());
 
             // 替换加密文件名为解密后的文件名
             for (name, content) in &self.entrys {
+                self.hooks.check()?;
                 let mut out_s = content.clone();
                 for (k, v) in &self.uncompressed {
                     out_s = out_s.replace(k, v);
                 }
 
-                let output_file = subdir.join(name);
-                let mut file = File::create(output_file)?;
-                file.write_all(out_s.as_bytes())?;
+                let output_file = safe_join(&subdir, name)?;
+                self.hooks
+                    .write(sink, &output_file, out_s.as_bytes())
+                    .context(ErrorStage::Write, sink.location(&output_file).display())?;
             }
         }
 
@@ -59,61 +71,84 @@ impl LpkLoader {
     }
 }
 
-impl LpkLoader {
+impl<R: Read + Seek> LpkLoader<R> {
     /// 解压旧版格式的LPK文件
-    pub(crate) fn extract_legacy<P: AsRef<Path>>(&mut self, output_dir: P) -> Result<()> {
-        let output_dir = output_dir.as_ref();
-
+    pub(crate) fn extract_legacy(&mut self, sink: &dyn ExtractSink) -> Result<()> {
         warn!("Deprecated/unknown lpk format detected. Attempting with STD_1_0 format...");
         warn!("Decryption may not work for some packs, even though this script outputs all files.");
-
-        // 如果不是加密的，直接解压所有文件
         if !self.encrypted {
             info!("lpk is not encrypted, extracting all files...");
-            let file = File::open(&self.lpk_path)?;
-            let mut archive = ZipArchive::new(file)?;
-            archive.extract(output_dir)?;
-            return Ok(());
         }
 
-        // 处理加密文件
-        let file = File::open(&self.lpk_path)?;
-        let mut archive = ZipArchive::new(file)?;
-
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
+        for i in 0..self.archive.len() {
+            self.hooks.check()?;
+            let file = self.archive.by_index(i).context(ErrorStage::Decrypt, format!("#{i}"))?;
+            // 跳过目录
+            if file.is_dir() {
+                continue;
+            }
+            // 和标准格式一样拒绝绝对路径和越过输出目录的条目名
+            safe_join(Path::new(""), file.name())?;
             let outpath = match file.enclosed_name() {
                 Some(path) => path.to_owned(),
-                None => continue,
+                None => Err(LpkError::UnsafePath(file.name().to_string()))?,
             };
 
-            // 跳过没有扩展名的文件
-            if outpath.extension().is_none() {
-                continue;
-            }
-
-            let subdir = output_dir.join(outpath.parent().unwrap_or_else(|| Path::new("")).to_path_buf());
-            let output_file_path = subdir.join(outpath.file_name().unwrap());
+            let (entry, size) = (file.name().to_string(), file.size());
+            drop(file);
 
-            safe_mkdir(&subdir)?;
-
-            // 文本文件直接解压
+            // 未加密的LPK和文本文件直接解压
             let extension = outpath.extension().and_then(|s| s.to_str()).unwrap_or("");
-            if extension == "json" || extension == "mlve" || extension == "txt" {
-                info!("Extracting {} -> {}", outpath.display(), output_file_path.display());
-                let mut outfile = File::create(&output_file_path)?;
-                std::io::copy(&mut file, &mut outfile)?;
+            let plain = !self.encrypted || extension == "json" || extension == "mlve" || extension == "txt";
+            let name = outpath.to_str().unwrap_or("");
+            let scheme = match plain {
+                true => KeyScheme::Plain,
+                false => self.key_scheme(name)?,
+            };
+            // 较大的条目分块解密, 需要从密文恢复密钥时仍然完整读取
+            if size >= STREAM_THRESHOLD && scheme != KeyScheme::Recovered {
+                let (keystream, hooks, total) = (self.keystream(name, scheme), &self.hooks, self.archive.len());
+                stream_entry(&mut self.archive, hooks, &entry, keystream, sink, |head, bytes| {
+                    if plain {
+                        info!("Extracting {}", outpath.display());
+                        return Ok(outpath.clone());
+                    }
+                    info!("Decrypting {}", outpath.display());
+                    warn_unrecognized(hooks, name, scheme, head);
+                    hooks.emit(ExtractEvent::EntryDecrypted { name: name.to_string(), bytes, done: i + 1, total });
+                    let mut output_file_path = outpath.clone();
+                    if needs_extension(&outpath) {
+                        output_file_path.set_extension(FileType::sniff(head).extension());
+                    }
+                    Ok(output_file_path)
+                })?;
+                continue;
+            }
+            let buffer = read_raw(&mut self.archive, &entry, &self.hooks)?;
+            if plain {
+                info!("Extracting {}", outpath.display());
+                self.hooks.write(sink, &outpath, &buffer).context(ErrorStage::Write, sink.location(&outpath).display())?;
+                continue;
             }
-            else {
-                // 解密其他文件
-                info!("Decrypting {} -> {}", outpath.display(), output_file_path.display());
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
 
-                let decrypted_data = self.decrypt_data(outpath.to_str().unwrap_or(""), &buffer)?;
-                let mut outfile = File::create(&output_file_path)?;
-                outfile.write_all(&decrypted_data)?;
+            // 解密其他文件
+            info!("Decrypting {}", outpath.display());
+            let decrypted_data = self.decrypt_raw(name, buffer)?;
+            // 旧版格式不一定使用支持的加密方式, 无法识别时仍然输出
+            warn_unrecognized(&self.hooks, name, scheme, &decrypted_data);
+            self.emit(ExtractEvent::EntryDecrypted {
+                name: name.to_string(),
+                bytes: decrypted_data.len() as u64,
+                done: i + 1,
+                total: self.archive.len(),
+            });
+            let mut output_file_path = outpath.clone();
+            if needs_extension(&outpath) {
+                output_file_path.set_extension(FileType::sniff(&decrypted_data).extension());
             }
+            self.hooks
+                .write(sink, &output_file_path, &decrypted_data)
+                .context(ErrorStage::Write, sink.location(&output_file_path).display())?;
         }
 
         Ok(())
@@ -0,0 +1,260 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// 增量解压的记录文件, 位于输出根目录
+pub const INCREMENTAL_MANIFEST: &str = ".lpk-manifest.json";
+
+/// 增量解压的记录
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// LPK 和密钥参数的指纹, 变化时所有记录失效
+    source: String,
+    /// 角色目录 -> 条目名 -> 写出的文件
+    dirs: BTreeMap<String, BTreeMap<String, ManifestRecord>>,
+}
+
+/// 一个条目写出的文件
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ManifestRecord {
+    /// 条目的 CRC32
+    crc32: u32,
+    /// 条目解压后的大小
+    size: u64,
+    /// 相对角色目录的输出文件名
+    output: String,
+    /// 输出文件的大小
+    output_size: u64,
+    /// 输出文件的 MD5
+    output_md5: String,
+}
+
+/// 写入的文件的大小和 MD5
+type Digests = Arc<Mutex<HashMap<PathBuf, (u64, String)>>>;
+
+/// 增量解压的设置, 上一次和本次解压的记录
+#[derive(Clone, Default)]
+pub(crate) struct IncrementalState {
+    /// 是否增量解压
+    enabled: bool,
+    previous: Manifest,
+    current: Manifest,
+    /// 本次解压写入的文件的大小和 MD5, 由写入回调记录
+    digests: Digests,
+}
+
+impl IncrementalState {
+    /// 本次解压写入的文件的大小和 MD5
+    fn digest(&self, path: &Path) -> Option<(u64, String)> {
+        self.digests.lock().ok()?.get(path).cloned()
+    }
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 设置是否增量解压, 默认关闭
+    ///
+    /// 开启后在输出根目录写入 [`INCREMENTAL_MANIFEST`], 再次解压时跳过来源条目和输出文件都没有变化的条目.
+    /// 只有支持 [`ExtractSink::read`] 的写入目标能够跳过. 目前只对标准格式生效, 旧版格式会发出警告并完整解压.
+    pub fn set_incremental(&mut self, incremental: bool) {
+        self.incremental.enabled = incremental;
+        self.hooks.set_write_callback(incremental.then(|| digest_callback(self.incremental.digests.clone())));
+    }
+
+    /// 是否增量解压
+    pub(crate) fn is_incremental(&self) -> bool {
+        self.incremental.enabled
+    }
+
+    /// 开始解压时读取上一次的记录
+    pub(crate) fn load_manifest(&mut self, sink: &dyn ExtractSink) {
+        self.incremental.previous = Manifest::default();
+        self.incremental.current = Manifest::default();
+        if let Ok(mut digests) = self.incremental.digests.lock() {
+            digests.clear();
+        }
+        if !self.incremental.enabled {
+            return;
+        }
+        if !self.standard_layout() {
+            let message = "Incremental extraction is not supported for this lpk format, extracting all files".to_string();
+            warn!("{}", message);
+            self.emit(ExtractEvent::Warning { message });
+            return;
+        }
+        let source = self.source_fingerprint();
+        let previous = match sink.read(Path::new(INCREMENTAL_MANIFEST)) {
+            Ok(bytes) => match serde_json::from_slice::<Manifest>(&bytes) {
+                Ok(o) if o.source == source => o,
+                Ok(_) => {
+                    debug!("Source of {} changed, extracting all files", INCREMENTAL_MANIFEST);
+                    Manifest::default()
+                }
+                Err(e) => {
+                    warn!("Failed to parse {}: {}", INCREMENTAL_MANIFEST, e);
+                    Manifest::default()
+                }
+            },
+            Err(_) => Manifest::default(),
+        };
+        self.incremental.previous = previous;
+        self.incremental.current = Manifest { source, dirs: BTreeMap::new() };
+    }
+
+    /// 解压完成后写出本次的记录
+    pub(crate) fn save_manifest(&self, sink: &dyn ExtractSink) -> Result<()> {
+        if !self.incremental.enabled || !self.standard_layout() {
+            return Ok(());
+        }
+        let path = Path::new(INCREMENTAL_MANIFEST);
+        let json = self.manifest_json()?;
+        sink.write(path, json.as_bytes()).context(ErrorStage::Write, sink.location(path).display())
+    }
+
+    /// 本次解压的记录
+    pub(crate) fn manifest_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.incremental.current)?)
+    }
+
+    /// 跳过来源和输出都没有变化的条目, 沿用上一次的输出文件名, 返回仍然需要解密的条目
+    pub(crate) fn skip_unchanged(&mut self, dir: &Path, files: Vec<String>, sink: &dyn ExtractSink) -> Result<Vec<String>> {
+        if !self.incremental.enabled {
+            return Ok(files);
+        }
+        let mut pending = Vec::new();
+        for file in files {
+            match self.keep_unchanged(dir, &file, sink)? {
+                Some((output, bytes)) => {
+                    trace!("Skipped unchanged {}", output.display());
+                    self.emit(ExtractEvent::Skipped { path: sink.location(&output), bytes });
+                }
+                None => pending.push(file),
+            }
+        }
+        Ok(pending)
+    }
+
+    /// 与 [`Self::skip_unchanged`] 相同但不通知观察者, 用于解压计划
+    pub(crate) fn skip_unchanged_quietly(
+        &mut self,
+        dir: &Path,
+        files: Vec<String>,
+        sink: &dyn ExtractSink,
+    ) -> Result<Vec<String>> {
+        let mut pending = Vec::new();
+        for file in files {
+            if !self.incremental.enabled || self.keep_unchanged(dir, &file, sink)?.is_none() {
+                pending.push(file);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// 来源和输出都没有变化时沿用上一次的记录和输出文件名, 返回输出路径和大小
+    fn keep_unchanged(&mut self, dir: &Path, file: &str, sink: &dyn ExtractSink) -> Result<Option<(PathBuf, u64)>> {
+        let key = dir_key(dir);
+        let Some(record) = self.incremental.previous.dirs.get(&key).and_then(|d| d.get(file)).cloned()
+        else {
+            return Ok(None);
+        };
+        let (crc32, size) = self.entry_source(file)?;
+        let output = safe_join(dir, &record.output)?;
+        if record.crc32 != crc32 || record.size != size || !output_unchanged(sink, &output, &record) {
+            return Ok(None);
+        }
+        let bytes = record.output_size;
+        self.uncompressed.insert(file.to_string(), record.output.clone());
+        self.incremental.current.dirs.entry(key).or_default().insert(file.to_string(), record);
+        Ok(Some((output, bytes)))
+    }
+
+    /// 记录本次写出的条目, 需要在恢复文件名之后调用
+    pub(crate) fn record_outputs(&mut self, dir: &Path) -> Result<()> {
+        if !self.incremental.enabled {
+            return Ok(());
+        }
+        let key = dir_key(dir);
+        let files = self.archive.file_names().map(|s| s.to_string()).collect::<Vec<_>>();
+        for file in files {
+            let Some(output) = self.uncompressed.get(&file).cloned()
+            else {
+                continue;
+            };
+            let Some((output_size, output_md5)) = self.incremental.digest(&safe_join(dir, &output)?)
+            else {
+                continue;
+            };
+            self.record_output(&key, file, output, output_size, output_md5)?;
+        }
+        Ok(())
+    }
+
+    /// 记录计划写出的条目, 用于在解压计划中预计记录文件的大小
+    pub(crate) fn plan_output(&mut self, dir: &Path, file: &str, data: &[u8]) -> Result<()> {
+        let output = self.uncompressed[file].clone();
+        self.record_output(&dir_key(dir), file.to_string(), output, data.len() as u64, format!("{:x}", md5::compute(data)))
+    }
+
+    fn record_output(&mut self, key: &str, file: String, output: String, output_size: u64, output_md5: String) -> Result<()> {
+        let (crc32, size) = self.entry_source(&file)?;
+        let record = ManifestRecord { crc32, size, output, output_size, output_md5 };
+        self.incremental.current.dirs.entry(key.to_string()).or_default().insert(file, record);
+        Ok(())
+    }
+
+    /// 条目的 CRC32 和解压后的大小, 不需要读取内容
+    fn entry_source(&mut self, file: &str) -> Result<(u32, u64)> {
+        let entry = self.archive.by_name(file).context(ErrorStage::Decrypt, file)?;
+        Ok((entry.crc32(), entry.size()))
+    }
+
+    /// LPK 和密钥参数的指纹
+    fn source_fingerprint(&self) -> String {
+        let source = format!(
+            "{}\n{}\n{}\n{}\n{:?}",
+            self.lpk_type,
+            self.mlve_config.id,
+            self.config.file_id,
+            self.config.meta_data,
+            self.entry_scheme()
+        );
+        format!("{:x}", md5::compute(source))
+    }
+}
+
+/// 在写入时计算每个文件的大小和 MD5 的回调
+fn digest_callback(digests: Digests) -> WriteCallback {
+    Arc::new(move |path| {
+        let (digests, path) = (digests.clone(), path.to_path_buf());
+        let (mut size, mut md5) = (0, Some(md5::Context::new()));
+        Box::new(move |chunk| match chunk {
+            Some(data) => {
+                size += data.len() as u64;
+                if let Some(md5) = &mut md5 {
+                    md5.consume(data);
+                }
+            }
+            None => {
+                if let (Some(md5), Ok(mut digests)) = (md5.take(), digests.lock()) {
+                    digests.insert(path.clone(), (size, format!("{:x}", md5.compute())));
+                }
+            }
+        })
+    })
+}
+
+/// 输出文件是否仍然是上一次写出的内容
+fn output_unchanged(sink: &dyn ExtractSink, output: &Path, record: &ManifestRecord) -> bool {
+    match sink.read(output) {
+        Ok(data) => data.len() as u64 == record.output_size && format!("{:x}", md5::compute(&data)) == record.output_md5,
+        Err(_) => false,
+    }
+}
+
+/// 记录中使用 `/` 分隔的角色目录
+fn dir_key(dir: &Path) -> String {
+    dir.to_string_lossy().replace('\\', "/")
+}
@@ -0,0 +1,95 @@
+use super::{verify::looks_decrypted, *};
+use serde::Serialize;
+
+/// 依次尝试的密钥规则
+const CANDIDATES: [KeyScheme; 4] = [KeyScheme::Standard, KeyScheme::Steam, KeyScheme::Zero, KeyScheme::Plain];
+
+/// LPK的基本信息
+#[derive(Clone, Debug, Serialize)]
+pub struct LpkInfo {
+    /// LPK类型
+    pub lpk_type: LpkType,
+    /// 包的 id
+    pub id: String,
+    /// 包的名称
+    pub name: String,
+    /// 压缩包中的条目数量
+    pub entries: usize,
+    /// 加密条目使用的密钥规则, 未知类型的LPK为探测结果, 探测失败时为 `None`
+    pub key_scheme: Option<KeyScheme>,
+    /// 密钥规则是否由探测得到
+    pub detected: bool,
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 获取LPK的基本信息
+    pub fn info(&self) -> LpkInfo {
+        LpkInfo {
+            lpk_type: self.lpk_type.clone(),
+            id: self.mlve_config.id.clone(),
+            name: self.mlve_config.name.clone(),
+            entries: self.archive.len(),
+            key_scheme: self.entry_scheme(),
+            detected: self.detected_scheme.is_some(),
+        }
+    }
+
+    /// 对未知类型的LPK, 用探测条目依次尝试所有密钥规则, 记录第一个能得到有效内容的规则
+    pub(crate) fn detect_key_scheme(&mut self) {
+        self.detected_scheme = None;
+        if !matches!(self.lpk_type, LpkType::Unknown(_)) {
+            return;
+        }
+        let Some((probe, model_json)) = self.probe_entry()
+        else {
+            return;
+        };
+        let data = match read_raw(&mut self.archive, &probe, &self.hooks) {
+            Ok(o) => o,
+            Err(e) => {
+                warn!("Failed to read probe entry {}: {}", probe, e.english());
+                return;
+            }
+        };
+        for scheme in CANDIDATES {
+            let mut data = data.clone();
+            if let Some(keystream) = self.keystream(&probe, scheme) {
+                keystream.decrypt_in_place(&mut data);
+            }
+            if looks_decrypted(model_json, &data) {
+                debug!("Detected key scheme {:?} with probe {}", scheme, probe);
+                self.detected_scheme = Some(scheme);
+                return;
+            }
+        }
+        warn!("Unable to detect key scheme of {} with probe {}", self.lpk_type, probe);
+    }
+
+    /// 选择探测条目, 优先使用可以完整校验的模型 JSON, 其次是不按扩展名视为明文的条目
+    fn probe_entry(&self) -> Option<(String, bool)> {
+        if let Some(path) = self.costume_paths().next() {
+            return Some((path.to_string(), true));
+        }
+        let config = hashed_filename("config.mlve");
+        let names = self.archive.file_names().filter(|name| !name.ends_with('/') && *name != "config.mlve" && *name != config);
+        let names = names.collect::<Vec<_>>();
+        let name = names.iter().find(|name| !is_text_name(name)).or(names.first())?;
+        Some((name.to_string(), false))
+    }
+
+    /// 是否按角色和服装组织, 探测到密钥规则并且有服装列表的未知类型也按标准格式处理
+    pub(crate) fn standard_layout(&self) -> bool {
+        self.lpk_type.is_standard() || (self.detected_scheme.is_some() && self.costume_paths().next().is_some())
+    }
+
+    /// 配置中存在于压缩包里的服装模型 JSON
+    pub(crate) fn costume_paths(&self) -> impl Iterator<Item = &str> {
+        let costumes = self.mlve_config.list.iter().flat_map(|chara| chara.costume.iter());
+        costumes.map(|c| c.path.as_str()).filter(|path| self.archive.index_for_name(path).is_some())
+    }
+}
+
+/// 旧版格式中按扩展名视为明文的文本文件
+pub(crate) fn is_text_name(name: &str) -> bool {
+    name.ends_with(".json") || name.ends_with(".mlve") || name.ends_with(".txt")
+}
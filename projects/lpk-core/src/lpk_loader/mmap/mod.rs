@@ -0,0 +1,24 @@
+use super::*;
+use memmap2::Mmap;
+use std::io::Cursor;
+
+/// 内存映射的LPK文件
+pub type MappedLpk = Cursor<Mmap>;
+
+impl LpkLoader<MappedLpk> {
+    /// 通过内存映射打开LPK文件, 条目直接从映射的内存中读取, 不经过文件读取的系统调用
+    ///
+    /// 加载器存在期间文件不能被其他程序截断或修改.
+    pub fn open_mmap(lpk_path: &Path) -> Result<Self> {
+        let config = lpk_path.with_file_name("config").with_extension("json");
+        LpkLoader::open_mmap_with_config(lpk_path, &config)
+    }
+
+    /// 通过内存映射打开LPK文件, 并使用指定的 config.json
+    pub fn open_mmap_with_config(lpk_path: &Path, config_path: &Path) -> Result<Self> {
+        let file = File::open(lpk_path).context(ErrorStage::Open, lpk_path.display())?;
+        // SAFETY: 映射是只读的, 文件在加载器存在期间不被修改由调用方保证, 见上面的文档
+        let map = unsafe { Mmap::map(&file) }.context(ErrorStage::Open, lpk_path.display())?;
+        LpkLoader::open_reader(Cursor::new(map), lpk_path, config_path)
+    }
+}
@@ -0,0 +1,93 @@
+use super::*;
+use std::sync::{Arc, Mutex};
+use tokio::task::spawn_blocking;
+
+/// 异步的LPK加载器, 读取和解密都在阻塞线程池中进行
+///
+/// 和 [`LpkLoader`] 一样按需从文件读取条目, 不会把 LPK 完整读入内存, 同一个加载器上的操作依次执行.
+/// 解压时输出文件和解密一起在阻塞线程池中写入, 这也是 `tokio::fs` 的实现方式, 但避免了每个文件的往返.
+pub struct AsyncLpkLoader<R = File> {
+    inner: Arc<Mutex<LpkLoader<R>>>,
+}
+
+impl AsyncLpkLoader {
+    /// 打开LPK文件, Steam Workshop LPK 读取同目录的 config.json
+    pub async fn open(lpk_path: impl AsRef<Path>) -> Result<Self> {
+        let lpk_path = lpk_path.as_ref();
+        let config = lpk_path.with_file_name("config").with_extension("json");
+        AsyncLpkLoader::open_with_config(lpk_path, config).await
+    }
+
+    /// 打开LPK文件, 并使用指定的 config.json
+    pub async fn open_with_config(lpk_path: impl AsRef<Path>, config_path: impl AsRef<Path>) -> Result<Self> {
+        let lpk_path = lpk_path.as_ref().to_path_buf();
+        let config_path = config_path.as_ref().to_path_buf();
+        let loader = blocking(move || LpkLoader::open_with_config(&lpk_path, &config_path)).await?;
+        Ok(AsyncLpkLoader::from(loader))
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> AsyncLpkLoader<R> {
+    /// 在阻塞线程池中使用同步的加载器, 可用于修改解压选项或调用其他同步接口
+    pub async fn with_loader<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut LpkLoader<R>) -> Result<T> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        blocking(move || f(&mut inner.lock().unwrap_or_else(|e| e.into_inner()))).await
+    }
+
+    /// 获取MLVE配置
+    pub async fn mlve_config(&self) -> Result<MLveConfig> {
+        self.with_loader(|loader| Ok(loader.mlve_config().clone())).await
+    }
+
+    /// 列出所有条目, 见 [`LpkLoader::entries`]
+    pub async fn entries(&self) -> Result<Vec<LpkEntry>> {
+        self.with_loader(|loader| loader.entries()).await
+    }
+
+    /// 读取并解密一个条目, 见 [`LpkLoader::read_entry`]
+    pub async fn read_entry(&self, name: &str) -> Result<Vec<u8>> {
+        let name = name.to_string();
+        self.with_loader(move |loader| loader.read_entry(&name)).await
+    }
+
+    /// 解压LPK文件到指定目录
+    pub async fn extract(&self, output_dir: impl AsRef<Path>) -> Result<()> {
+        let output_dir = output_dir.as_ref().to_path_buf();
+        tokio::fs::create_dir_all(&output_dir).await.context(ErrorStage::Write, output_dir.display())?;
+        self.with_loader(move |loader| loader.extract_to(&DirectorySink::new(&output_dir))).await
+    }
+
+    /// 解压LPK到任意写入目标
+    pub async fn extract_to(&self, sink: Arc<dyn ExtractSink>) -> Result<()> {
+        self.with_loader(move |loader| loader.extract_to(sink.as_ref())).await
+    }
+}
+
+impl<R> Clone for AsyncLpkLoader<R> {
+    fn clone(&self) -> Self {
+        AsyncLpkLoader { inner: self.inner.clone() }
+    }
+}
+
+impl<R> From<LpkLoader<R>> for AsyncLpkLoader<R> {
+    fn from(loader: LpkLoader<R>) -> Self {
+        AsyncLpkLoader { inner: Arc::new(Mutex::new(loader)) }
+    }
+}
+
+/// 在阻塞线程池中执行, 任务 panic 时在调用方继续 panic, 运行时关闭时返回 [`LpkError::Cancelled`]
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match spawn_blocking(f).await {
+        Ok(o) => o,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(LpkError::Cancelled),
+    }
+}
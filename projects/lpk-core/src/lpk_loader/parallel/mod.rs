@@ -0,0 +1,121 @@
+use super::*;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    mpsc::sync_channel,
+    Mutex,
+};
+
+/// 发送给工作线程的任务: 密钥流, 密钥规则, 是否是模型 JSON, 条目名和密文
+type Task = (Option<Keystream>, KeyScheme, bool, String, Vec<u8>);
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 设置是否并行解密条目, 默认关闭
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    /// 并行解密给定的条目
+    ///
+    /// 压缩包只在当前线程读取, 解密和写入分发给工作线程, 通道容量限制了同时驻留内存的条目数量.
+    /// 较大的条目在当前线程分块解密后直接写入, 不经过通道.
+    pub(crate) fn decrypt_all_parallel(&mut self, output: &Path, all_files: Vec<String>, sink: &dyn ExtractSink) -> Result<()> {
+        let tasks = all_files
+            .into_iter()
+            .map(|file| {
+                let scheme = self.key_scheme(&file)?;
+                let stream = self.stream_output(output, &file, scheme)?;
+                Ok((self.keystream(&file, scheme), scheme, self.is_model_json(&file), file, stream))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        let (sender, receiver) = sync_channel::<Task>(workers);
+        let receiver = Mutex::new(receiver);
+        let names = Mutex::new(Vec::new());
+        let (total, done, failed) = (tasks.len(), AtomicUsize::new(0), AtomicBool::new(false));
+        let (hooks, shared) = (&self.hooks, &self.shared);
+        let restored = &self.uncompressed;
+        let archive = &mut self.archive;
+        let process = |(keystream, scheme, model_json, file, mut buffer): Task| -> Result<()> {
+            hooks.check()?;
+            if let Some(keystream) = keystream {
+                keystream.decrypt_in_place(&mut buffer);
+            }
+            match model_json {
+                true => verify_model_json(&file, scheme, &buffer)?,
+                false => warn_unrecognized(hooks, &file, scheme, &buffer),
+            }
+            let bytes = buffer.len() as u64;
+            let count = done.fetch_add(1, Ordering::Relaxed) + 1;
+            hooks.emit(ExtractEvent::EntryDecrypted { name: file.clone(), bytes, done: count, total });
+            let name = output_name(restored, &file, FileType::sniff(&buffer));
+            let output = safe_join(output, &name)?;
+            names.lock().map_err(|_| LpkError::UnknownError)?.push((file, name));
+            match shared.write_asset(hooks, sink, &output, &buffer) {
+                Ok(_) => debug!("Exported {}", output.display()),
+                Err(err) => {
+                    let output = sink.location(&output);
+                    error!("Failed to write file {}: {}", output.display(), err);
+                    let message = format!("Failed to write file {}: {}", output.display(), err);
+                    hooks.emit(ExtractEvent::Warning { message });
+                }
+            }
+            Ok(())
+        };
+        let result = std::thread::scope(|scope| {
+            let handles = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| -> Result<()> {
+                        let mut result = Ok(());
+                        loop {
+                            let task = receiver.lock().map_err(|_| LpkError::UnknownError)?.recv();
+                            let Ok(task) = task
+                            else {
+                                return result;
+                            };
+                            // 出错后继续取出剩余任务, 避免读取线程阻塞在发送上
+                            if result.is_ok() {
+                                result = process(task);
+                                if result.is_err() {
+                                    failed.store(true, Ordering::Relaxed);
+                                }
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut result = (|| -> Result<()> {
+                for (keystream, scheme, model_json, file, stream) in tasks {
+                    // 已经有工作线程出错, 不再读取剩余条目
+                    if failed.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Some(path) = stream {
+                        stream_entry(archive, hooks, &file, keystream, sink, |head, bytes| {
+                            warn_unrecognized(hooks, &file, scheme, head);
+                            let count = done.fetch_add(1, Ordering::Relaxed) + 1;
+                            hooks.emit(ExtractEvent::EntryDecrypted { name: file.clone(), bytes, done: count, total });
+                            Ok(path)
+                        })?;
+                        continue;
+                    }
+                    let buffer = read_raw(archive, &file, hooks)?;
+                    // 发送失败说明所有工作线程都已退出, 错误会在下面汇总
+                    if sender.send((keystream, scheme, model_json, file, buffer)).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            })();
+            drop(sender);
+            for handle in handles {
+                let joined = handle.join().unwrap_or(Err(LpkError::UnknownError));
+                if result.is_ok() {
+                    result = joined;
+                }
+            }
+            result
+        });
+        self.uncompressed.extend(names.into_inner().map_err(|_| LpkError::UnknownError)?);
+        result
+    }
+}
@@ -0,0 +1,171 @@
+use super::{
+    dedupe::{is_shareable, shared_asset_path, shared_model_json},
+    *,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// 解压计划, 列出解压时将会写出的所有文件而不实际写入
+#[derive(Clone, Debug, Serialize)]
+pub struct ExtractionPlan {
+    /// 输出目录
+    pub output_dir: PathBuf,
+    /// LPK类型
+    pub lpk_type: LpkType,
+    /// 将要写出的文件, 按输出路径排序
+    pub files: Vec<PlannedFile>,
+}
+
+/// 解压计划中的单个输出文件
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedFile {
+    /// 输出路径
+    pub output: PathBuf,
+    /// 来源条目, 生成的文件 (如 mapping.json) 没有来源
+    pub source: Option<String>,
+    /// 使用的密钥规则
+    pub scheme: KeyScheme,
+    /// 预计写出的字节数
+    pub size: u64,
+    /// 是否会覆盖已经存在的文件
+    pub overwrite: bool,
+}
+
+impl ExtractionPlan {
+    /// 序列化为 JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 生成解压到指定目录的计划, 不会写入任何文件
+    ///
+    /// 开启增量解压时不列出会被跳过的文件, 并列出记录文件.
+    pub fn plan(&mut self, output_dir: &Path) -> Result<ExtractionPlan> {
+        // 模拟解压会改动文件名映射和增量解压的记录, 结束后还原
+        let saved = (self.uncompressed.clone(), self.incremental.clone());
+        let files = match self.standard_layout() {
+            true => self.plan_standard(output_dir),
+            false => self.plan_legacy(output_dir),
+        };
+        (self.uncompressed, self.incremental) = saved;
+        let mut files = files?;
+        files.sort_by(|a, b| a.output.cmp(&b.output));
+        Ok(ExtractionPlan { output_dir: output_dir.to_path_buf(), lpk_type: self.lpk_type.clone(), files })
+    }
+
+    /// 按 `extract_standard` 的流程模拟解压, 共享资源需要解密后才能确定位置
+    fn plan_standard(&mut self, output_dir: &Path) -> Result<Vec<PlannedFile>> {
+        let (layout, incremental) = (self.shared.layout, self.is_incremental());
+        let sink = DirectorySink::new(output_dir);
+        self.load_manifest(&sink);
+        let mut tasks = Vec::new();
+        for (chara, costume) in self.selected_costumes()? {
+            if !costume.path.is_empty() {
+                let dir = safe_join(Path::new(""), &character_dir_name(&chara.character))?;
+                tasks.push((dir, costume.path.clone(), model_json_name(&chara.character, &costume.name)));
+            }
+        }
+        // 每个输出路径只会写出一次, 以第一次写出为准, 路径都相对输出根目录
+        let mut planned = BTreeMap::<PathBuf, PlannedFile>::new();
+        let mut shared = HashMap::<PathBuf, PathBuf>::new();
+        let mut dirs = Vec::new();
+        for (dir, model_json, output) in tasks {
+            let selected = self.costume_entries(&model_json)?;
+            // 增量解压时没有变化的条目不会写出
+            let selected = self.skip_unchanged_quietly(&dir, selected, &sink)?;
+            self.restore_names(&model_json, &selected)?;
+            for name in &selected {
+                let output = safe_join(&dir, &self.uncompressed[name])?;
+                if planned.contains_key(&output) {
+                    continue;
+                }
+                let scheme = self.key_scheme(name)?;
+                let size = self.archive.by_name(name).context(ErrorStage::Decrypt, name)?.size();
+                let shareable = layout != AssetLayout::Copy && is_shareable(&output);
+                let data = match shareable || incremental {
+                    true => self.read_entry(name)?,
+                    false => vec![],
+                };
+                if shareable {
+                    let target = shared_asset_path(&output, &data);
+                    planned
+                        .entry(target.clone())
+                        .or_insert_with(|| planned_file(output_dir, target.clone(), Some(name), scheme, size));
+                    shared.insert(output.clone(), target);
+                    if layout == AssetLayout::SharedPaths {
+                        continue;
+                    }
+                }
+                if incremental {
+                    self.plan_output(&dir, name, &data)?;
+                }
+                planned.insert(output.clone(), planned_file(output_dir, output, Some(name), scheme, size));
+            }
+
+            let json = String::from_utf8_lossy(&self.read_entry(&model_json)?).to_string();
+            let json = match layout {
+                AssetLayout::SharedPaths => {
+                    shared_model_json(self.rewrite_references(json), &dir, &|p| shared.get(p).cloned())?
+                }
+                _ => self.rewrite_references(json),
+            };
+            let scheme = self.key_scheme(&model_json)?;
+            let output = safe_join(&dir, &output)?;
+            let size = json.len() as u64;
+            planned.entry(output.clone()).or_insert_with(|| planned_file(output_dir, output, Some(&model_json), scheme, size));
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        // 映射在所有服装的文件名确定后写出
+        let size = self.mapping_json()?.len() as u64;
+        for dir in dirs {
+            let output = dir.join("mapping.json");
+            planned.insert(output.clone(), planned_file(output_dir, output, None, KeyScheme::Plain, size));
+        }
+        if incremental {
+            let output = PathBuf::from(INCREMENTAL_MANIFEST);
+            let size = self.manifest_json()?.len() as u64;
+            planned.insert(output.clone(), planned_file(output_dir, output, None, KeyScheme::Plain, size));
+        }
+        Ok(planned.into_values().collect())
+    }
+
+    /// 按 `extract_legacy` 的流程模拟解压
+    fn plan_legacy(&mut self, output_dir: &Path) -> Result<Vec<PlannedFile>> {
+        let mut files = Vec::new();
+        for i in 0..self.archive.len() {
+            let (name, size, outpath) = {
+                let file = self.archive.by_index_raw(i).context(ErrorStage::Decrypt, format!("#{i}"))?;
+                if file.is_dir() {
+                    continue;
+                }
+                safe_join(Path::new(""), file.name())?;
+                match file.enclosed_name() {
+                    Some(path) => (file.name().to_string(), file.size(), path),
+                    None => Err(LpkError::UnsafePath(file.name().to_string()))?,
+                }
+            };
+            if !self.encrypted {
+                files.push(planned_file(output_dir, outpath, Some(&name), KeyScheme::Plain, size));
+                continue;
+            }
+            let scheme = self.key_scheme(outpath.to_str().unwrap_or(""))?;
+            let mut output = outpath.clone();
+            if scheme != KeyScheme::Plain && needs_extension(&outpath) {
+                output.set_extension(self.sniff_entry(&name).extension());
+            }
+            files.push(planned_file(output_dir, output, Some(&name), scheme, size));
+        }
+        Ok(files)
+    }
+}
+
+/// `output` 相对 `output_dir` 时会拼接到输出目录下
+fn planned_file(output_dir: &Path, output: PathBuf, source: Option<&String>, scheme: KeyScheme, size: u64) -> PlannedFile {
+    let output = output_dir.join(output);
+    let overwrite = output.exists();
+    PlannedFile { output, source: source.cloned(), scheme, size, overwrite }
+}
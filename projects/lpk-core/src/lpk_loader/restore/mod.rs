@@ -0,0 +1,150 @@
+use super::*;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 在写出之前确定条目的输出文件名
+    ///
+    /// 先根据识别出的文件类型为加密文件名补全扩展名, 再沿模型 JSON 的引用关系分配可读的文件名.
+    pub(crate) fn restore_names(&mut self, model_json: &str, files: &[String]) -> Result<()> {
+        for file in files {
+            if !self.uncompressed.contains_key(file) {
+                let file_type = self.sniff_entry(file);
+                let name = output_name(&self.uncompressed, file, file_type);
+                self.uncompressed.insert(file.clone(), name);
+            }
+        }
+        for (hashed, current, readable) in self.plan_restore(model_json)? {
+            trace!("Restored {} -> {}", current, readable);
+            self.uncompressed.insert(hashed, readable);
+        }
+        Ok(())
+    }
+
+    /// 计算需要恢复的文件名, 返回 `(加密文件名, 当前文件名, 可读文件名)`
+    pub(crate) fn plan_restore(&mut self, model_json: &str) -> Result<Vec<(String, String, String)>> {
+        let json = match serde_json::from_slice::<Value>(&self.read_entry(model_json)?) {
+            Ok(o) => o,
+            Err(e) => {
+                warn!("Failed to parse model json {}: {}", model_json, e);
+                return Ok(vec![]);
+            }
+        };
+        let mut used = self.uncompressed.values().cloned().collect::<HashSet<_>>();
+        let mut renames = Vec::new();
+        for (hashed, readable) in model_references(&json) {
+            let Some(current) = self.uncompressed.get(&hashed).cloned()
+            else {
+                continue;
+            };
+            // 已经在之前的服装中恢复过
+            if !is_encrypted_file(Path::new(&current).file_stem().and_then(|s| s.to_str()).unwrap_or(""))
+                || renames.iter().any(|(h, _, _)| h == &hashed)
+            {
+                continue;
+            }
+            let extension = Path::new(&current).extension().and_then(|s| s.to_str()).unwrap_or("bin");
+            let readable = unique_name(&readable.replace("{ext}", extension), &used);
+            used.insert(readable.clone());
+            renames.push((hashed, current, readable));
+        }
+        Ok(renames)
+    }
+
+    /// 写出加密文件名到恢复后文件名的映射
+    pub(crate) fn write_mapping(&self, dir: &Path, sink: &dyn ExtractSink) -> Result<()> {
+        let path = dir.join("mapping.json");
+        let mapping = self.mapping_json()?;
+        self.hooks.write(sink, &path, mapping.as_bytes()).context(ErrorStage::Write, sink.location(&path).display())
+    }
+
+    /// 加密文件名到恢复后文件名的映射
+    pub(crate) fn mapping_json(&self) -> Result<String> {
+        let mapping = self.uncompressed.iter().collect::<BTreeMap<_, _>>();
+        Ok(serde_json::to_string_pretty(&mapping)?)
+    }
+}
+
+/// 收集模型 JSON 引用的文件及其可读文件名, `{ext}` 会被替换为识别出的扩展名
+pub(crate) fn model_references(json: &Value) -> Vec<(String, String)> {
+    let mut refs = Vec::new();
+    let mut push = |value: &Value, name: String| {
+        if let Some(s) = value.as_str().filter(|s| !s.is_empty()) {
+            refs.push((s.to_string(), name));
+        }
+    };
+    match json.get("FileReferences") {
+        // Cubism 3+ 的 model3.json
+        Some(files) => {
+            push(&files["Moc"], "model.moc3".to_string());
+            for (i, texture) in as_array(&files["Textures"]).iter().enumerate() {
+                push(texture, format!("textures/texture_{i:02}.{{ext}}"));
+            }
+            push(&files["Physics"], "model.physics3.json".to_string());
+            push(&files["Pose"], "model.pose3.json".to_string());
+            push(&files["UserData"], "model.userdata3.json".to_string());
+            push(&files["DisplayInfo"], "model.cdi3.json".to_string());
+            for (i, expression) in as_array(&files["Expressions"]).iter().enumerate() {
+                let name = safe_name(expression["Name"].as_str(), || format!("expression_{i}"));
+                push(&expression["File"], format!("expressions/{name}.exp3.json"));
+            }
+            for (group, motions) in files["Motions"].as_object().into_iter().flatten() {
+                let group = safe_name(Some(group), || "motion".to_string());
+                for (i, motion) in as_array(motions).iter().enumerate() {
+                    push(&motion["File"], format!("motions/{group}_{i}.motion3.json"));
+                    push(&motion["Sound"], format!("sounds/{group}_{i}.{{ext}}"));
+                }
+            }
+        }
+        // Cubism 2 的 model.json
+        None => {
+            push(&json["model"], "model.moc".to_string());
+            for (i, texture) in as_array(&json["textures"]).iter().enumerate() {
+                push(texture, format!("textures/texture_{i:02}.{{ext}}"));
+            }
+            push(&json["physics"], "model.physics.json".to_string());
+            push(&json["pose"], "model.pose.json".to_string());
+            for (i, expression) in as_array(&json["expressions"]).iter().enumerate() {
+                let name = safe_name(expression["name"].as_str(), || format!("expression_{i}"));
+                push(&expression["file"], format!("expressions/{name}.exp.json"));
+            }
+            for (group, motions) in json["motions"].as_object().into_iter().flatten() {
+                let group = safe_name(Some(group), || "motion".to_string());
+                for (i, motion) in as_array(motions).iter().enumerate() {
+                    push(&motion["file"], format!("motions/{group}_{i}.mtn"));
+                    push(&motion["sound"], format!("sounds/{group}_{i}.{{ext}}"));
+                }
+            }
+        }
+    }
+    refs
+}
+
+fn as_array(value: &Value) -> &[Value] {
+    value.as_array().map(|v| v.as_slice()).unwrap_or_default()
+}
+
+/// 将模型 JSON 中的名称转换为可以安全用作文件名的形式
+fn safe_name(name: Option<&str>, fallback: impl FnOnce() -> String) -> String {
+    match name {
+        Some(name) => sanitize_file_name(name, &fallback()),
+        None => fallback(),
+    }
+}
+
+/// 文件名被其他资源占用时追加序号
+fn unique_name(name: &str, used: &HashSet<String>) -> String {
+    if !used.contains(name) {
+        return name.to_string();
+    }
+    let (dir, file) = name.rsplit_once('/').map(|(d, f)| (format!("{d}/"), f)).unwrap_or_default();
+    let file = if dir.is_empty() { name } else { file };
+    let (stem, extension) = file.split_once('.').unwrap_or((file, ""));
+    (1..)
+        .map(|i| match extension {
+            "" => format!("{dir}{stem}_{i}"),
+            _ => format!("{dir}{stem}_{i}.{extension}"),
+        })
+        .find(|n| !used.contains(n))
+        .unwrap_or_default()
+}
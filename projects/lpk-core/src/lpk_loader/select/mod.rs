@@ -0,0 +1,119 @@
+use super::*;
+use crate::{LpkCharacter, LpkCostume};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+
+/// 要解压的角色和服装, 默认解压全部
+///
+/// 角色按 id 或名称匹配, 服装按名称或模型 JSON 的条目名匹配.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// 解压所有服装的角色
+    characters: Vec<String>,
+    /// 只解压指定服装的角色
+    costumes: Vec<(String, String)>,
+}
+
+impl Selection {
+    /// 选择全部角色和服装
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// 选择角色的所有服装
+    pub fn character(mut self, character: impl Into<String>) -> Self {
+        self.characters.push(character.into());
+        self
+    }
+
+    /// 选择角色的一套服装
+    pub fn costume(mut self, character: impl Into<String>, costume: impl Into<String>) -> Self {
+        self.costumes.push((character.into(), costume.into()));
+        self
+    }
+
+    /// 是否选择了全部角色和服装
+    pub fn is_all(&self) -> bool {
+        self.characters.is_empty() && self.costumes.is_empty()
+    }
+
+    /// 是否选择了角色的这套服装
+    pub fn selects(&self, character: &LpkCharacter, costume: &LpkCostume) -> bool {
+        let chara = |s: &str| s == character.id || s == character.character;
+        self.is_all()
+            || self.characters.iter().any(|c| chara(c))
+            || self.costumes.iter().any(|(c, n)| chara(c) && (n == &costume.name || n == &costume.path))
+    }
+}
+
+impl Display for Selection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let characters = self.characters.iter().cloned();
+        let costumes = self.costumes.iter().map(|(c, n)| format!("{c}/{n}"));
+        f.write_str(&characters.chain(costumes).collect::<Vec<_>>().join(", "))
+    }
+}
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 设置要解压的角色和服装, 只选择部分服装时只解密它们的模型 JSON 引用的条目
+    pub fn set_selection(&mut self, selection: Selection) {
+        self.selection = selection;
+    }
+
+    /// 选中的服装, 返回 `(角色, 服装, 模型 JSON)`, 部分选择没有匹配任何服装时返回 [`LpkError::ModelMissing`]
+    pub(crate) fn selected_costumes(&self) -> Result<Vec<(&LpkCharacter, &LpkCostume)>> {
+        let mut selected = Vec::new();
+        for chara in self.mlve_config.list.as_slice() {
+            for costume in chara.costume.iter().filter(|c| self.selection.selects(chara, c)) {
+                selected.push((chara, costume));
+            }
+        }
+        if selected.is_empty() && !self.selection.is_all() {
+            Err(LpkError::ModelMissing(self.selection.to_string()))?
+        }
+        Ok(selected)
+    }
+
+    /// 解压服装时需要解密的条目, 选择全部时为所有条目
+    pub(crate) fn costume_entries(&mut self, model_json: &str) -> Result<Vec<String>> {
+        let all = self.archive.file_names().map(|s| s.to_string()).collect::<Vec<_>>();
+        if self.selection.is_all() {
+            return Ok(all);
+        }
+        let json = match serde_json::from_slice::<Value>(&self.read_entry(model_json)?) {
+            Ok(o) => o,
+            Err(e) => {
+                warn!("Failed to parse model json {}, extracting all entries: {}", model_json, e);
+                return Ok(all);
+            }
+        };
+        let mut entries = Vec::new();
+        collect_references(&json, &mut |s| {
+            if let Some(name) = self.resolve_reference(model_json, s) {
+                if name != model_json && !entries.contains(&name) {
+                    entries.push(name);
+                }
+            }
+        });
+        Ok(entries)
+    }
+
+    /// 模型 JSON 中的引用对应的条目名, 也尝试相对模型 JSON 所在目录查找
+    pub(crate) fn resolve_reference(&self, model_json: &str, reference: &str) -> Option<String> {
+        let name = match model_json.rsplit_once('/') {
+            Some((dir, _)) if self.archive.index_for_name(reference).is_none() => format!("{dir}/{reference}"),
+            _ => reference.to_string(),
+        };
+        self.archive.index_for_name(&name).map(|_| name)
+    }
+}
+
+/// 遍历 JSON 中的所有字符串
+fn collect_references(value: &Value, f: &mut impl FnMut(&str)) {
+    match value {
+        Value::String(s) => f(s),
+        Value::Array(array) => array.iter().for_each(|v| collect_references(v, f)),
+        Value::Object(object) => object.values().for_each(|v| collect_references(v, f)),
+        _ => {}
+    }
+}
@@ -0,0 +1,73 @@
+use super::*;
+use serde::de::IgnoredAny;
+
+impl<R: Read + Seek> LpkLoader<R> {
+    /// 判断条目是否是某个服装的模型 JSON
+    pub(crate) fn is_model_json(&self, name: &str) -> bool {
+        self.mlve_config.list.iter().any(|chara| chara.costume.iter().any(|c| c.path == name))
+    }
+
+    /// 用模型 JSON 校验密钥规则, 每种规则只校验一次
+    ///
+    /// 资源可能是无法识别的二进制格式, 不能单独判断密钥是否正确, 而密钥错误时模型 JSON 无法解析.
+    /// 明文和从密文恢复的密钥不需要校验, 没有模型 JSON 时无法校验.
+    pub(crate) fn verify_key(&mut self, scheme: KeyScheme) -> Result<()> {
+        if matches!(scheme, KeyScheme::Plain | KeyScheme::Recovered) || self.verified_keys.contains(&scheme) {
+            return Ok(());
+        }
+        let Some(probe) = self.costume_paths().find(|path| !self.is_plain_entry(path)).map(|path| path.to_string())
+        else {
+            return Ok(());
+        };
+        let mut data = read_raw(&mut self.archive, &probe, &self.hooks)?;
+        if let Some(keystream) = self.keystream(&probe, scheme) {
+            keystream.decrypt_in_place(&mut data);
+        }
+        verify_model_json(&probe, scheme, &data)?;
+        debug!("Verified key scheme {:?} with {}", scheme, probe);
+        self.verified_keys.insert(scheme);
+        Ok(())
+    }
+}
+
+/// 校验模型 JSON 的解密结果, 密钥错误时解密出的是随机字节, 无法解析为 JSON
+pub(crate) fn verify_model_json(name: &str, scheme: KeyScheme, data: &[u8]) -> Result<()> {
+    match scheme == KeyScheme::Plain || looks_decrypted(true, data) {
+        true => Ok(()),
+        false => Err(LpkError::DecryptionFailed { entry: name.to_string(), scheme }),
+    }
+}
+
+/// 条目内容既不是已知的文件类型也不是文本时发出警告
+///
+/// 密钥已经通过模型 JSON 校验, 这里只可能是不认识的二进制格式, 不中断解压.
+/// 只检查开头时末尾被截断的 UTF-8 字符不影响判断.
+pub(crate) fn warn_unrecognized(hooks: &ExtractHooks, name: &str, scheme: KeyScheme, head: &[u8]) {
+    if scheme == KeyScheme::Plain || looks_decrypted(false, head) {
+        return;
+    }
+    let message = format!("Unrecognized content in {name} (scheme {scheme:?}), the key may be wrong");
+    warn!("{}", message);
+    hooks.emit(ExtractEvent::Warning { message });
+}
+
+/// 内容是否像是正确解密的结果
+pub(crate) fn looks_decrypted(model_json: bool, data: &[u8]) -> bool {
+    match model_json {
+        true => {
+            let json = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+            serde_json::from_slice::<IgnoredAny>(json).is_ok()
+        }
+        false => FileType::sniff(data) != FileType::Unknown || is_text(data),
+    }
+}
+
+/// 是否是不含控制字符的 UTF-8 文本, 末尾不完整的字符视为被截断
+fn is_text(data: &[u8]) -> bool {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return false,
+    };
+    text.chars().all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// config.mlve 中 `type` 字段表示的包格式
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum LpkType {
+    /// `STD_1_0`, 旧版标准格式
+    Std1_0,
+    /// `STD2_0`, 标准格式
+    Std2_0,
+    /// `STM_1_0`, Steam Workshop 格式
+    Stm1_0,
+    /// 无法识别的格式, 保留原始文本
+    Unknown(String),
+}
+
+impl LpkType {
+    /// config.mlve 中的原始文本
+    pub fn as_str(&self) -> &str {
+        match self {
+            LpkType::Std1_0 => "STD_1_0",
+            LpkType::Std2_0 => "STD2_0",
+            LpkType::Stm1_0 => "STM_1_0",
+            LpkType::Unknown(s) => s,
+        }
+    }
+
+    /// 是否是按角色和服装组织的标准格式 (STD2_0 或 STM_1_0)
+    pub fn is_standard(&self) -> bool {
+        matches!(self, LpkType::Std2_0 | LpkType::Stm1_0)
+    }
+}
+
+impl Default for LpkType {
+    fn default() -> Self {
+        LpkType::Unknown(String::new())
+    }
+}
+
+impl From<String> for LpkType {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "STD_1_0" => LpkType::Std1_0,
+            "STD2_0" => LpkType::Std2_0,
+            "STM_1_0" => LpkType::Stm1_0,
+            _ => LpkType::Unknown(s),
+        }
+    }
+}
+
+impl From<&str> for LpkType {
+    fn from(s: &str) -> Self {
+        LpkType::from(s.to_string())
+    }
+}
+
+impl From<LpkType> for String {
+    fn from(t: LpkType) -> Self {
+        match t {
+            LpkType::Unknown(s) => s,
+            t => t.as_str().to_string(),
+        }
+    }
+}
+
+impl Display for LpkType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// config.mlve 中 `encrypt` 字段表示的加密方式
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum EncryptMode {
+    /// `true`, 条目按包格式的密钥规则加密
+    Enabled,
+    /// `false`, Steam Workshop LPK 使用固定的 0 密钥
+    Disabled,
+    /// `encrypt`, 旧版格式的加密标记
+    Encrypt,
+    /// 无法识别的取值, 保留原始文本
+    Unknown(String),
+}
+
+impl EncryptMode {
+    /// config.mlve 中的原始文本
+    pub fn as_str(&self) -> &str {
+        match self {
+            EncryptMode::Enabled => "true",
+            EncryptMode::Disabled => "false",
+            EncryptMode::Encrypt => "encrypt",
+            EncryptMode::Unknown(s) => s,
+        }
+    }
+}
+
+impl Default for EncryptMode {
+    fn default() -> Self {
+        EncryptMode::Unknown(String::new())
+    }
+}
+
+impl From<String> for EncryptMode {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "true" => EncryptMode::Enabled,
+            "false" => EncryptMode::Disabled,
+            "encrypt" => EncryptMode::Encrypt,
+            _ => EncryptMode::Unknown(s),
+        }
+    }
+}
+
+impl From<&str> for EncryptMode {
+    fn from(s: &str) -> Self {
+        EncryptMode::from(s.to_string())
+    }
+}
+
+impl From<EncryptMode> for String {
+    fn from(m: EncryptMode) -> Self {
+        match m {
+            EncryptMode::Unknown(s) => s,
+            m => m.as_str().to_string(),
+        }
+    }
+}
+
+impl Display for EncryptMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use serde_json::Value;
+use tracing::{debug, trace};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{
+    configs::{LpkCharacter, LpkCostume},
+    errors::{Context, ErrorStage, LpkError, Result},
+    helpers::{decrypt_in_place, hashed_filename, make_key},
+    EncryptMode, LpkConfig, LpkType, MLveConfig,
+};
+
+/// LPK文件写入器，负责将 Cubism 模型目录打包为加密的LPK文件
+///
+/// 生成的文件可以被 Live2DViewerEX 和 [`LpkLoader`](crate::LpkLoader) 读取.
+pub struct LpkWriter {
+    /// MLVE配置
+    mlve_config: MLveConfig,
+    /// 用户配置（用于Steam Workshop LPK）
+    config: Option<LpkConfig>,
+    /// 待写入的条目, 加密文件名 -> 明文内容
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl LpkWriter {
+    /// 创建标准格式 (STD2_0) 的LPK写入器
+    pub fn new(id: &str, name: &str) -> Self {
+        LpkWriter {
+            mlve_config: MLveConfig {
+                r#type: LpkType::Std2_0,
+                name: name.to_string(),
+                id: id.to_string(),
+                encrypt: EncryptMode::Enabled,
+                version: "2.0".to_string(),
+                list: vec![],
+                ..Default::default()
+            },
+            config: None,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// 创建 Steam Workshop 格式 (STM_1_0) 的LPK写入器, 密钥由 `fileId` 和 `metaData` 参与生成
+    pub fn steam(id: &str, name: &str, config: LpkConfig) -> Self {
+        let mut writer = LpkWriter::new(id, name);
+        writer.mlve_config.r#type = LpkType::Stm1_0;
+        writer.mlve_config.version = "1.0".to_string();
+        writer.config = Some(config);
+        writer
+    }
+
+    /// 获取将要写入的 MLVE 配置
+    pub fn mlve_config(&self) -> &MLveConfig {
+        &self.mlve_config
+    }
+
+    /// 添加角色, 可选地附带头像图片
+    pub fn add_character(&mut self, character: &str, avatar: Option<&Path>) -> Result<()> {
+        let avatar = match avatar {
+            Some(path) => {
+                let data = std::fs::read(path).context(ErrorStage::Open, path.display())?;
+                let name = self.entry_name(&format!("{character}/avatar"));
+                self.entries.insert(name.clone(), data);
+                name
+            }
+            None => String::new(),
+        };
+        match self.mlve_config.list.iter_mut().find(|c| c.character == character) {
+            Some(chara) => chara.avatar = avatar,
+            None => self.mlve_config.list.push(LpkCharacter {
+                id: self.mlve_config.list.len().to_string(),
+                character: character.to_string(),
+                avatar,
+                costume: vec![],
+                ..Default::default()
+            }),
+        }
+        Ok(())
+    }
+
+    /// 从 Cubism 模型目录添加服装, 角色不存在时会自动创建
+    ///
+    /// 目录中的所有文件都会被打包, 模型 JSON 中引用的相对路径会被替换为加密文件名.
+    pub fn add_costume(&mut self, character: &str, costume: &str, model_dir: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        collect_files(model_dir, Path::new(""), &mut files)?;
+        files.sort();
+        let model_json = match files.iter().find(|f| is_model_json(f)) {
+            Some(s) => s.clone(),
+            None => Err(LpkError::ModelMissing(model_dir.display().to_string()))?,
+        };
+        // 先为所有文件分配加密文件名, 再改写模型 JSON 中的引用
+        let mut hashed = BTreeMap::new();
+        for file in &files {
+            let name = self.entry_name(&format!("{character}/{costume}/{file}"));
+            trace!("{} -> {}", file, name);
+            hashed.insert(file.clone(), name);
+        }
+        for file in &files {
+            let path = model_dir.join(file);
+            let data = std::fs::read(&path).context(ErrorStage::Open, path.display())?;
+            let data = match file == &model_json {
+                true => rewrite_references(&data, file, &hashed).context(ErrorStage::Open, path.display())?,
+                false => data,
+            };
+            self.entries.insert(hashed[file].clone(), data);
+        }
+        if !self.mlve_config.list.iter().any(|c| c.character == character) {
+            self.add_character(character, None)?;
+        }
+        let path = hashed[&model_json].clone();
+        debug!("Packed costume `{}({})` from {}", costume, character, model_dir.display());
+        if let Some(chara) = self.mlve_config.list.iter_mut().find(|c| c.character == character) {
+            chara.costume.push(LpkCostume { name: costume.to_string(), path, ..Default::default() });
+        }
+        Ok(())
+    }
+
+    /// 将LPK写入任意可定位的输出流
+    pub fn write<W: Write + Seek>(&self, writer: W) -> Result<W> {
+        let mut zip = ZipWriter::new(writer);
+        let options = SimpleFileOptions::default();
+        // config.mlve 以明文存储
+        let config = hashed_filename("config.mlve");
+        zip.start_file(config.as_str(), options).context(ErrorStage::Write, &config)?;
+        zip.write_all(serde_json::to_string_pretty(&self.mlve_config)?.as_bytes()).context(ErrorStage::Write, &config)?;
+        for (name, data) in &self.entries {
+            let mut data = data.clone();
+            // 加密与解密是同一个异或操作
+            decrypt_in_place(self.entry_key(name), &mut data);
+            zip.start_file(name.as_str(), options).context(ErrorStage::Write, name)?;
+            zip.write_all(&data).context(ErrorStage::Write, name)?;
+        }
+        zip.finish().context(ErrorStage::Write, "")
+    }
+
+    /// 将LPK保存到指定路径, Steam Workshop LPK 会同时在旁边写入 `config.json`
+    pub fn save(&self, lpk_path: &Path) -> Result<()> {
+        let file = File::create(lpk_path).context(ErrorStage::Write, lpk_path.display())?;
+        self.write(file).map_err(|e| e.with_context(ErrorStage::Write, lpk_path.display()))?;
+        if let Some(config) = &self.config {
+            let mut config = config.clone();
+            if config.lpk_file.is_empty() {
+                config.lpk_file = lpk_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            }
+            let config_path = lpk_path.with_file_name("config").with_extension("json");
+            std::fs::write(&config_path, serde_json::to_string(&config)?).context(ErrorStage::Write, config_path.display())?;
+        }
+        Ok(())
+    }
+
+    /// 生成条目的加密文件名
+    fn entry_name(&self, logical: &str) -> String {
+        format!("{}.bin", hashed_filename(&format!("{}/{logical}", self.mlve_config.id)))
+    }
+
+    /// 生成条目的加密密钥, 与 `LpkLoader` 的解密规则保持一致
+    fn entry_key(&self, filename: &str) -> i128 {
+        match &self.config {
+            Some(config) => make_key(&format!("{}{}{filename}{}", self.mlve_config.id, config.file_id, config.meta_data)),
+            None => make_key(&format!("{}{filename}", self.mlve_config.id)),
+        }
+    }
+}
+
+/// 递归收集目录中的所有文件, 返回以 `/` 分隔的相对路径
+fn collect_files(root: &Path, relative: &Path, files: &mut Vec<String>) -> Result<()> {
+    let dir = root.join(relative);
+    for entry in std::fs::read_dir(&dir).context(ErrorStage::Open, dir.display())? {
+        let entry = entry.context(ErrorStage::Open, dir.display())?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type().context(ErrorStage::Open, entry.path().display())?.is_dir() {
+            collect_files(root, &path, files)?;
+        }
+        else {
+            files.push(to_slash(&path));
+        }
+    }
+    Ok(())
+}
+
+/// 判断是否是模型 JSON 文件 (Cubism 3+ 的 `*.model3.json` 或 Cubism 2 的 `*.model.json`)
+fn is_model_json(file: &str) -> bool {
+    let file = file.to_ascii_lowercase();
+    file.ends_with(".model3.json") || file.ends_with(".model.json") || file == "model.json"
+}
+
+/// 将模型 JSON 中指向目录内文件的相对路径替换为加密文件名
+fn rewrite_references(data: &[u8], model_json: &str, hashed: &BTreeMap<String, String>) -> Result<Vec<u8>> {
+    let mut json: Value = serde_json::from_slice(data)?;
+    let base = Path::new(model_json).parent().unwrap_or_else(|| Path::new(""));
+    rewrite_value(&mut json, base, hashed);
+    Ok(serde_json::to_vec_pretty(&json)?)
+}
+
+fn rewrite_value(value: &mut Value, base: &Path, hashed: &BTreeMap<String, String>) {
+    match value {
+        Value::String(s) => {
+            if let Some(name) = normalize(&base.join(s.as_str())).and_then(|p| hashed.get(&p)) {
+                *s = name.clone();
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| rewrite_value(v, base, hashed)),
+        Value::Object(map) => map.values_mut().for_each(|v| rewrite_value(v, base, hashed)),
+        _ => {}
+    }
+}
+
+/// 规范化相对路径, 越过根目录时返回 `None`
+fn normalize(path: &Path) -> Option<String> {
+    let mut parts = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(s) => parts.push(s),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !parts.pop() {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(to_slash(&parts))
+}
+
+fn to_slash(path: &Path) -> String {
+    path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
@@ -0,0 +1,150 @@
+//! 已知明文密钥恢复
+//!
+//! Steam Workshop LPK 的密钥需要 config.json 中的 `fileId` 和 `metaData`, 但密钥流只由 16 位种子决定,
+//! 而模型和资源文件的开头都是可以预测的内容, 所以可以直接从密文中恢复每个条目的种子.
+//!
+//! 较短的文件头只有两三个字节的已知明文, 每 128 个条目就会有一个被随机种子误中,
+//! 所以每个候选种子都要通过文件头的结构校验, 有多个种子通过时视为无法恢复.
+
+use crate::{keystream::next_state, Keystream};
+use serde::de::IgnoredAny;
+use std::collections::BTreeSet;
+
+/// 结构校验需要解密的开头字节数, 也是 [`recover_head_keystream`] 需要的开头长度
+pub const HEADER_LEN: usize = 64;
+
+/// 对解密后开头的结构校验
+type HeaderCheck = fn(&[u8]) -> bool;
+
+/// 常见资源文件的已知明文前缀, 以及对应的结构校验
+const KNOWN_HEADERS: &[(&[u8], HeaderCheck)] = &[
+    (b"\x89PNG\r\n\x1a\n", |_| true),
+    (b"# Live2D", |_| true),
+    (b"MOC3", is_moc3_header),
+    (b"OggS", is_ogg_header),
+    (b"RIFF", is_wave_header),
+    (b"moc", is_moc_header),
+    (b"ID3", is_id3_header),
+];
+
+/// 尝试从密文中恢复密钥流, 无法识别明文类型或者有多个可能的密钥流时返回 `None`
+pub fn recover_keystream(data: &[u8]) -> Option<Keystream> {
+    // JSON 只有一个字节的已知明文, 但完整解析几乎不会误判, 所以优先尝试
+    recover_seed(data, json_seeds(data, true))
+}
+
+/// 只根据条目开头恢复密钥流, 用于不读取完整条目时识别文件类型
+///
+/// JSON 无法完整解析, 只要求解密后的开头是文本, 结果不如 [`recover_keystream`] 可靠.
+pub fn recover_head_keystream(head: &[u8]) -> Option<Keystream> {
+    let head = &head[..head.len().min(HEADER_LEN)];
+    recover_seed(head, json_seeds(head, false))
+}
+
+/// 没有 JSON 种子时改用文件头, 只有唯一的种子时恢复成功
+fn recover_seed(data: &[u8], json: BTreeSet<u16>) -> Option<Keystream> {
+    let seeds = match json.is_empty() {
+        true => header_seeds(data),
+        false => json,
+    };
+    match seeds.len() {
+        1 => seeds.first().map(|&seed| Keystream::from_seed(seed)),
+        _ => None,
+    }
+}
+
+/// 能把数据解密为 JSON 的种子, `complete` 为 `false` 时只检查开头
+fn json_seeds(data: &[u8], complete: bool) -> BTreeSet<u16> {
+    let mut seeds = BTreeSet::new();
+    for prefix in [b"\xEF\xBB\xBF{".as_slice(), b"{"] {
+        for seed in candidate_seeds(data, prefix) {
+            // 先用开头排除明显不是文本的结果, 避免每个候选都解密整个条目
+            if !is_text_head(&decrypt_head(data, seed)) {
+                continue;
+            }
+            if !complete {
+                seeds.insert(seed);
+                continue;
+            }
+            let mut plain = data.to_vec();
+            Keystream::from_seed(seed).decrypt_in_place(&mut plain);
+            let json = plain.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&plain);
+            if serde_json::from_slice::<IgnoredAny>(json).is_ok() {
+                seeds.insert(seed);
+            }
+        }
+    }
+    seeds
+}
+
+/// 能把数据开头解密为已知文件头且通过结构校验的种子
+fn header_seeds(data: &[u8]) -> BTreeSet<u16> {
+    let mut seeds = BTreeSet::new();
+    for (prefix, valid) in KNOWN_HEADERS {
+        for seed in candidate_seeds(data, prefix) {
+            if valid(&decrypt_head(data, seed)) {
+                seeds.insert(seed);
+            }
+        }
+    }
+    seeds
+}
+
+/// 使用种子解密数据的开头
+fn decrypt_head(data: &[u8], seed: u16) -> Vec<u8> {
+    let mut head = data[..data.len().min(HEADER_LEN)].to_vec();
+    Keystream::from_seed(seed).decrypt_in_place(&mut head);
+    head
+}
+
+/// 列出能把密文开头解密为 `prefix` 的所有种子
+fn candidate_seeds<'a>(data: &'a [u8], prefix: &'a [u8]) -> impl Iterator<Item = u16> + 'a {
+    let valid = data.len() >= prefix.len();
+    // 种子的低 8 位就是第一个密钥字节, 只需要枚举高 8 位
+    let low = data.first().map(|b| b ^ prefix[0]).unwrap_or(0) as u16;
+    (0..=255u16).filter(move |_| valid).map(move |high| high << 8 | low).filter(move |&seed| {
+        let mut k = seed as i128;
+        data[1..prefix.len()].iter().zip(&prefix[1..]).all(|(c, p)| {
+            k = next_state(k);
+            c ^ (k & 0xff) as u8 == *p
+        })
+    })
+}
+
+/// 开头是不含控制字符的 UTF-8 文本, 末尾允许被截断的多字节字符
+fn is_text_head(head: &[u8]) -> bool {
+    let text = match std::str::from_utf8(head) {
+        Ok(o) => o,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return false,
+    };
+    text.chars().all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+}
+
+/// moc3 文件头: 版本号, 字节序标记, 之后是填充的 0
+fn is_moc3_header(head: &[u8]) -> bool {
+    matches!(head.get(4..8), Some([1..=5, 0 | 1, 0, 0]))
+}
+
+/// Cubism 2 moc 文件头: `moc` 之后是格式版本
+fn is_moc_header(head: &[u8]) -> bool {
+    matches!(head.get(3), Some(6..=11))
+}
+
+/// Ogg 第一页的页头: 版本 0, 只有开始标记, 粒度位置为 0
+fn is_ogg_header(head: &[u8]) -> bool {
+    matches!(head.get(4..14), Some([0, 2, 0, 0, 0, 0, 0, 0, 0, 0]))
+}
+
+/// RIFF/WAVE 文件头: 类型为 `WAVE`, 第一个块是 `fmt `
+fn is_wave_header(head: &[u8]) -> bool {
+    head.get(8..16) == Some(b"WAVEfmt ")
+}
+
+/// ID3v2 标签头: 主版本 2 到 4, 次版本 0, 未定义的标记位为 0, 长度是 synchsafe 整数
+fn is_id3_header(head: &[u8]) -> bool {
+    match head.get(3..10) {
+        Some([2..=4, 0, flags, size @ ..]) => flags & 0x0F == 0 && size.iter().all(|b| b & 0x80 == 0),
+        _ => false,
+    }
+}
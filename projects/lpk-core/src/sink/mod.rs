@@ -0,0 +1,441 @@
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Debug, Formatter},
+    fs::File,
+    io::{Error, ErrorKind, Read, Result, Seek, Write},
+    path::{Component, Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// 解压结果的写入目标
+///
+/// 路径都是相对输出根目录的路径, 已经过安全检查. 开启并行解密时会在多个工作线程中同时写入.
+pub trait ExtractSink: Send + Sync {
+    /// 写入文件, 已存在时覆盖
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()>;
+    /// 从 `reader` 写入 `size` 字节的文件, 已存在时覆盖, 用于分块解密的大文件
+    ///
+    /// 默认先读取到内存再调用 [`ExtractSink::write`], 能够直接写入输出流的目标应当覆盖这个方法.
+    fn write_from(&self, path: &Path, size: u64, reader: &mut dyn Read) -> Result<()> {
+        let mut data = Vec::with_capacity(size.min(PREALLOCATE_LIMIT) as usize);
+        reader.read_to_end(&mut data)?;
+        self.write(path, &data)
+    }
+    /// 删除已经写入的文件, 用于取消解压时清理
+    fn remove(&self, path: &Path) -> Result<()>;
+    /// 文件是否已经存在, 取消解压时只删除本次新建的文件
+    fn exists(&self, _path: &Path) -> bool {
+        false
+    }
+    /// 读取已经存在的文件, 用于增量解压, 不支持读取的目标返回 [`ErrorKind::Unsupported`]
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Err(Error::new(ErrorKind::Unsupported, path.display().to_string()))
+    }
+    /// 创建指向 `target` 的硬链接或符号链接, 不支持链接的目标返回 [`ErrorKind::Unsupported`]
+    fn link(&self, _target: &Path, path: &Path, _symbolic: bool) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, path.display().to_string()))
+    }
+    /// 所有文件写入完成, 归档类的目标在这时写出
+    fn finish(&self) -> Result<()> {
+        Ok(())
+    }
+    /// 文件在事件中显示的位置
+    fn location(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+}
+
+/// 按条目或文件声明的大小预先分配的上限, 避免伪造的大小导致一次分配过多内存
+pub(crate) const PREALLOCATE_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// 写入文件系统中的目录
+#[derive(Clone, Debug)]
+pub struct DirectorySink {
+    root: PathBuf,
+}
+
+impl DirectorySink {
+    /// 写入到 `root` 目录下, 缺少的父目录会自动创建
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ExtractSink for DirectorySink {
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, data)
+    }
+
+    fn write_from(&self, path: &Path, _: u64, reader: &mut dyn Read) -> Result<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::io::copy(reader, &mut File::create(path)?).map(|_| ())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        std::fs::remove_file(self.root.join(path))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.root.join(path).symlink_metadata().is_ok()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(self.root.join(path))
+    }
+
+    fn link(&self, target: &Path, path: &Path, symbolic: bool) -> Result<()> {
+        let link = self.root.join(path);
+        if let Some(parent) = link.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match std::fs::remove_file(&link) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        match symbolic {
+            // 使用相对路径, 移动输出目录后链接仍然有效
+            true => symlink(&relative_path(path.parent().unwrap_or(Path::new("")), target), &link),
+            false => std::fs::hard_link(self.root.join(target), link),
+        }
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        match path.as_os_str().is_empty() {
+            true => self.root.clone(),
+            false => self.root.join(path),
+        }
+    }
+}
+
+/// 保存在内存中, 解压完成后通过 [`MemorySink::into_files`] 取出
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemorySink {
+    /// 创建空的内存目标
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取出所有文件, 相对路径 -> 内容
+    pub fn into_files(self) -> HashMap<PathBuf, Vec<u8>> {
+        self.files.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<PathBuf, Vec<u8>>>> {
+        self.files.lock().map_err(|_| Error::other("memory sink poisoned"))
+    }
+}
+
+impl ExtractSink for MemorySink {
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.lock()?.insert(path.to_path_buf(), data.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        match self.lock()?.remove(path) {
+            Some(_) => Ok(()),
+            None => Err(Error::new(ErrorKind::NotFound, path.display().to_string())),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.lock().map(|files| files.contains_key(path)).unwrap_or(false)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.lock()?.get(path) {
+            Some(data) => Ok(data.clone()),
+            None => Err(Error::new(ErrorKind::NotFound, path.display().to_string())),
+        }
+    }
+}
+
+/// 打包为 zip 压缩包
+///
+/// 解压时文件名在写出之前就已经确定, 每个文件写入时直接追加到压缩包中, 不会在内存中缓存.
+/// 已经写出的文件不能删除或覆盖.
+pub struct ZipSink<W: Write + Seek> {
+    archive: ArchiveSink<ZipWriter<W>, W>,
+}
+
+impl ZipSink<File> {
+    /// 创建 zip 文件, 没有调用 [`ExtractSink::finish`] 就被释放时删除这个文件
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut sink = ZipSink::new(File::create(path)?);
+        sink.archive.cleanup = Some(path.to_path_buf());
+        Ok(sink)
+    }
+}
+
+impl<W: Write + Seek + Send> ZipSink<W> {
+    /// 写入到任意可定位的输出流
+    pub fn new(writer: W) -> Self {
+        Self { archive: ArchiveSink::new(ZipWriter::new(writer)) }
+    }
+
+    /// 取回输出流, 只有写出完成后才会返回
+    pub fn into_inner(mut self) -> Option<W> {
+        self.archive.take_output()
+    }
+}
+
+impl<W: Write + Seek + Send> ExtractSink for ZipSink<W> {
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.archive.append(path, |zip| {
+            zip.start_file(archive_name(path), SimpleFileOptions::default()).map_err(Error::other)?;
+            zip.write_all(data)
+        })
+    }
+
+    fn write_from(&self, path: &Path, _: u64, reader: &mut dyn Read) -> Result<()> {
+        self.archive.append(path, |zip| {
+            zip.start_file(archive_name(path), SimpleFileOptions::default()).map_err(Error::other)?;
+            std::io::copy(reader, zip).map(|_| ())
+        })
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, path.display().to_string()))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.archive.exists(path)
+    }
+
+    fn finish(&self) -> Result<()> {
+        self.archive.finish(|zip| zip.finish().map_err(Error::other))
+    }
+}
+
+/// tar 包的压缩方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TarCompression {
+    /// 不压缩, `.tar`
+    #[default]
+    None,
+    /// gzip, `.tar.gz`
+    Gzip,
+    /// zstd, `.tar.zst`
+    Zstd,
+}
+
+impl TarCompression {
+    /// 根据文件名判断压缩方式
+    pub fn from_path(path: &Path) -> Self {
+        let name = path.file_name().map(|s| s.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            TarCompression::Gzip
+        }
+        else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            TarCompression::Zstd
+        }
+        else {
+            TarCompression::None
+        }
+    }
+}
+
+/// 打包为 tar 包, 可选 gzip 或 zstd 压缩
+///
+/// 与 [`ZipSink`] 一样在写入时直接追加到 tar 包中.
+pub struct TarSink<W: Write> {
+    archive: ArchiveSink<TarWriter<W>, W>,
+}
+
+impl TarSink<File> {
+    /// 创建 tar 文件, 根据扩展名选择压缩方式, 没有调用 [`ExtractSink::finish`] 就被释放时删除这个文件
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut sink = TarSink::new(File::create(path)?, TarCompression::from_path(path))?;
+        sink.archive.cleanup = Some(path.to_path_buf());
+        Ok(sink)
+    }
+}
+
+impl<W: Write + Send> TarSink<W> {
+    /// 写入到任意输出流
+    pub fn new(writer: W, compression: TarCompression) -> Result<Self> {
+        let writer = match compression {
+            TarCompression::None => TarWriter::None(tar::Builder::new(writer)),
+            TarCompression::Gzip => TarWriter::Gzip(tar::Builder::new(GzEncoder::new(writer, Compression::default()))),
+            TarCompression::Zstd => TarWriter::Zstd(tar::Builder::new(zstd::Encoder::new(writer, 0)?)),
+        };
+        Ok(Self { archive: ArchiveSink::new(writer) })
+    }
+
+    /// 取回输出流, 只有写出完成后才会返回
+    pub fn into_inner(mut self) -> Option<W> {
+        self.archive.take_output()
+    }
+}
+
+impl<W: Write + Send> ExtractSink for TarSink<W> {
+    fn write(&self, path: &Path, mut data: &[u8]) -> Result<()> {
+        self.write_from(path, data.len() as u64, &mut data)
+    }
+
+    /// tar 的文件头需要提前写入大小, `reader` 必须正好提供 `size` 字节
+    fn write_from(&self, path: &Path, size: u64, reader: &mut dyn Read) -> Result<()> {
+        self.archive.append(path, |tar| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(size);
+            header.set_mode(0o644);
+            match tar {
+                TarWriter::None(tar) => tar.append_data(&mut header, archive_name(path), reader),
+                TarWriter::Gzip(tar) => tar.append_data(&mut header, archive_name(path), reader),
+                TarWriter::Zstd(tar) => tar.append_data(&mut header, archive_name(path), reader),
+            }
+        })
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, path.display().to_string()))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.archive.exists(path)
+    }
+
+    fn finish(&self) -> Result<()> {
+        self.archive.finish(|tar| match tar {
+            TarWriter::None(tar) => tar.into_inner(),
+            TarWriter::Gzip(tar) => tar.into_inner()?.finish(),
+            TarWriter::Zstd(tar) => tar.into_inner()?.finish(),
+        })
+    }
+}
+
+impl<W: Write + Seek> Debug for ZipSink<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.archive.debug("ZipSink", f)
+    }
+}
+
+impl<W: Write> Debug for TarSink<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.archive.debug("TarSink", f)
+    }
+}
+
+/// 不同压缩方式的 tar 写入器
+enum TarWriter<W: Write> {
+    None(tar::Builder<W>),
+    Gzip(tar::Builder<GzEncoder<W>>),
+    Zstd(tar::Builder<zstd::Encoder<'static, W>>),
+}
+
+/// 归档类目标的公共部分, `A` 是正在写入的归档, 写出完成后得到输出流 `W`
+struct ArchiveSink<A, W> {
+    /// 正在写入的归档, 写出完成后为空
+    writer: Mutex<Option<A>>,
+    /// 写出完成后的输出流
+    output: Mutex<Option<W>>,
+    /// 已经写入的文件
+    written: Mutex<HashSet<PathBuf>>,
+    /// 没有写出完成就被释放时需要删除的文件
+    cleanup: Option<PathBuf>,
+}
+
+impl<A, W> ArchiveSink<A, W> {
+    fn new(writer: A) -> Self {
+        Self { writer: Mutex::new(Some(writer)), output: Mutex::new(None), written: Mutex::default(), cleanup: None }
+    }
+
+    /// 追加一个文件, 同一个文件不能写入两次
+    fn append(&self, path: &Path, f: impl FnOnce(&mut A) -> Result<()>) -> Result<()> {
+        let mut writer = self.writer.lock().map_err(|_| Error::other("archive sink poisoned"))?;
+        let writer = writer.as_mut().ok_or_else(|| Error::other("archive already finished"))?;
+        let mut written = self.written.lock().map_err(|_| Error::other("archive sink poisoned"))?;
+        if !written.insert(path.to_path_buf()) {
+            return Err(Error::new(ErrorKind::AlreadyExists, path.display().to_string()));
+        }
+        f(writer)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.written.lock().map(|written| written.contains(path)).unwrap_or(false)
+    }
+
+    /// 写出归档的结尾, 重复调用时什么都不做
+    fn finish(&self, f: impl FnOnce(A) -> Result<W>) -> Result<()> {
+        let mut writer = self.writer.lock().map_err(|_| Error::other("archive sink poisoned"))?;
+        let Some(inner) = writer.take()
+        else {
+            return Ok(());
+        };
+        let output = f(inner)?;
+        *self.output.lock().map_err(|_| Error::other("archive sink poisoned"))? = Some(output);
+        Ok(())
+    }
+
+    fn debug(&self, name: &str, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let finished = self.output.lock().map(|o| o.is_some()).unwrap_or(false);
+        f.debug_struct(name).field("finished", &finished).field("cleanup", &self.cleanup).finish_non_exhaustive()
+    }
+
+    /// 取出写出完成后的输出流
+    fn take_output(&mut self) -> Option<W> {
+        self.output.get_mut().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+impl<A, W> Drop for ArchiveSink<A, W> {
+    fn drop(&mut self) {
+        let finished = self.output.get_mut().map(|o| o.is_some()).unwrap_or(false);
+        let Some(path) = self.cleanup.take().filter(|_| !finished)
+        else {
+            return;
+        };
+        // 先关闭文件再删除
+        if let Ok(writer) = self.writer.get_mut() {
+            writer.take();
+        }
+        if let Err(e) = std::fs::remove_file(&path) {
+            tracing::warn!("Failed to remove unfinished archive {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// 从 `from` 目录指向 `to` 的相对路径, 两者都相对同一个根目录
+pub(crate) fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let depth = from.components().filter(|c| matches!(c, Component::Normal(_))).count();
+    let mut path = PathBuf::new();
+    (0..depth).for_each(|_| path.push(".."));
+    path.join(to)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_: &Path, link: &Path) -> Result<()> {
+    Err(Error::new(ErrorKind::Unsupported, link.display().to_string()))
+}
+
+/// 压缩包中使用 `/` 分隔的路径
+fn archive_name(path: &Path) -> String {
+    let parts = path.components().filter_map(|c| match c {
+        Component::Normal(part) => Some(part.to_string_lossy()),
+        _ => None,
+    });
+    parts.collect::<Vec<_>>().join("/")
+}
@@ -0,0 +1,254 @@
+use crate::{
+    errors::{Context, ErrorStage, LpkError, Result},
+    helpers::safe_join,
+    LpkConfig, LpkLoader,
+};
+use serde::Serialize;
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, warn};
+
+/// Live2DViewerEX 在 Steam 上的 AppID
+pub const WORKSHOP_APP_ID: &str = "616720";
+
+/// Steam Workshop 物品目录, 即 `workshop/content/616720/<id>/`
+///
+/// 目录中的 LPK, config.json 和预览图放在一起, Steam 的 `appworkshop_616720.acf` 记录了物品的大小和时间戳.
+#[derive(Clone, Debug, Serialize)]
+pub struct WorkshopItem {
+    /// 物品 id, 即目录名
+    pub id: String,
+    /// 物品目录
+    pub dir: PathBuf,
+    /// LPK 文件
+    pub lpk_path: PathBuf,
+    /// config.json 文件, 不存在时为 `None`
+    pub config_path: Option<PathBuf>,
+    /// config.json 的内容
+    pub config: Option<LpkConfig>,
+    /// 预览图
+    pub preview_path: Option<PathBuf>,
+    /// acf 清单中记录的安装信息
+    pub manifest: Option<WorkshopManifest>,
+}
+
+/// `appworkshop_616720.acf` 中单个物品的记录, 时间戳为 Unix 秒
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct WorkshopManifest {
+    /// 磁盘上的大小
+    pub size: Option<u64>,
+    /// 最后一次更新的时间
+    pub time_updated: Option<u64>,
+    /// 最后一次使用的时间
+    pub time_touched: Option<u64>,
+    /// 订阅者的 SteamID
+    pub subscribed_by: Option<String>,
+    /// 内容清单的 id
+    pub manifest: Option<String>,
+}
+
+impl WorkshopItem {
+    /// 打开物品目录, 查找 LPK, config.json 和预览图, 并读取上级目录中的 acf 清单
+    pub fn open(dir: &Path) -> Result<Self> {
+        let id = dir.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let config_path = Some(dir.join("config.json")).filter(|path| path.is_file());
+        let config = match &config_path {
+            Some(path) => {
+                let bytes = std::fs::read(path).context(ErrorStage::Config, path.display())?;
+                Some(LpkConfig::from_bytes(&bytes).context(ErrorStage::Config, path.display())?)
+            }
+            None => None,
+        };
+        let files = list_files(dir)?;
+        // config.json 不可信, 指向物品目录之外的文件名会被忽略
+        let named = |name: &str| match safe_join(dir, name) {
+            Ok(path) => Some(path).filter(|path| path.is_file()),
+            Err(_) if name.is_empty() => None,
+            Err(e) => {
+                warn!("Ignoring {} in {}: {}", name, dir.display(), e.english());
+                None
+            }
+        };
+        let lpk_path = match config.as_ref().and_then(|c| named(&c.lpk_file)) {
+            Some(path) => path,
+            None => match files.iter().find(|path| has_extension(path, &["lpk"])) {
+                Some(path) => path.clone(),
+                None => {
+                    Err(std::io::Error::new(ErrorKind::NotFound, "no .lpk file")).context(ErrorStage::Open, dir.display())?
+                }
+            },
+        };
+        let preview_path = match config.as_ref().and_then(|c| named(&c.preview_file)) {
+            Some(path) => Some(path),
+            None => files.iter().find(|path| has_extension(path, &["png", "jpg", "jpeg", "gif"])).cloned(),
+        };
+        let manifest = match dir.ancestors().nth(3).map(|steam| steam.join(format!("appworkshop_{WORKSHOP_APP_ID}.acf"))) {
+            Some(acf) if acf.is_file() => match WorkshopManifest::load(&acf, &id) {
+                Ok(o) => o,
+                Err(e) => {
+                    warn!("Failed to read workshop manifest {}: {}", acf.display(), e.english());
+                    None
+                }
+            },
+            _ => None,
+        };
+        debug!("Workshop item {} uses {}", id, lpk_path.display());
+        Ok(WorkshopItem { id, dir: dir.to_path_buf(), lpk_path, config_path, config, preview_path, manifest })
+    }
+
+    /// 物品标题, 来自 config.json
+    pub fn title(&self) -> Option<&str> {
+        self.config.as_ref().map(|c| c.title.as_str()).filter(|s| !s.is_empty())
+    }
+
+    /// 打开物品中的LPK, 缺少 config.json 时与 [`LpkLoader::open`] 一样尝试恢复密钥
+    pub fn loader(&self) -> Result<LpkLoader> {
+        let config = self.config_path.clone().unwrap_or_else(|| self.dir.join("config.json"));
+        LpkLoader::open_with_config(&self.lpk_path, &config)
+    }
+}
+
+impl WorkshopManifest {
+    /// 从 acf 清单中读取物品的记录, 清单中没有该物品时返回 `None`
+    pub fn load(acf: &Path, id: &str) -> Result<Option<Self>> {
+        let text = std::fs::read_to_string(acf).context(ErrorStage::Config, acf.display())?;
+        WorkshopManifest::parse(&text, id).map_err(|e| e.with_context(ErrorStage::Config, acf.display()))
+    }
+
+    /// 解析 acf 清单的文本, 合并 `WorkshopItemsInstalled` 和 `WorkshopItemDetails` 中的记录
+    pub fn parse(text: &str, id: &str) -> Result<Option<Self>> {
+        let root = parse_key_values(text)?;
+        let root = match root.first() {
+            Some((_, KeyValue::Object(o))) => o,
+            _ => return Ok(None),
+        };
+        let mut manifest = WorkshopManifest::default();
+        let mut found = false;
+        for section in ["WorkshopItemsInstalled", "WorkshopItemDetails"] {
+            let Some(item) = find_object(root, section).and_then(|o| find_object(o, id))
+            else {
+                continue;
+            };
+            found = true;
+            let number = |key: &str| find_value(item, key).and_then(|s| s.parse().ok());
+            let text = |key: &str| find_value(item, key).map(|s| s.to_string());
+            manifest.size = manifest.size.or(number("size"));
+            manifest.time_updated = manifest.time_updated.or(number("timeupdated"));
+            manifest.time_touched = manifest.time_touched.or(number("timetouched"));
+            manifest.subscribed_by = manifest.subscribed_by.or(text("subscribedby"));
+            manifest.manifest = manifest.manifest.or(text("manifest"));
+        }
+        Ok(Some(manifest).filter(|_| found))
+    }
+}
+
+/// Valve KeyValues 文本中的值
+enum KeyValue {
+    Value(String),
+    Object(Vec<(String, KeyValue)>),
+}
+
+fn find_object<'a>(object: &'a [(String, KeyValue)], key: &str) -> Option<&'a [(String, KeyValue)]> {
+    object.iter().find_map(|(k, v)| match v {
+        KeyValue::Object(o) if k.eq_ignore_ascii_case(key) => Some(o.as_slice()),
+        _ => None,
+    })
+}
+
+fn find_value<'a>(object: &'a [(String, KeyValue)], key: &str) -> Option<&'a str> {
+    object.iter().find_map(|(k, v)| match v {
+        KeyValue::Value(s) if k.eq_ignore_ascii_case(key) => Some(s.as_str()),
+        _ => None,
+    })
+}
+
+/// 解析 Valve KeyValues 文本, 只支持 acf 中出现的带引号字符串, 花括号和 `//` 注释
+fn parse_key_values(text: &str) -> Result<Vec<(String, KeyValue)>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => tokens.push((c, String::new())),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => return Err(acf_error("unterminated string")),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err(acf_error("unterminated string")),
+                    }
+                }
+                tokens.push(('"', s));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => return Err(acf_error(&format!("unexpected character `{c}`"))),
+        }
+    }
+    let mut tokens = tokens.into_iter();
+    let object = parse_object(&mut tokens)?;
+    match tokens.next() {
+        None => Ok(object),
+        Some(_) => Err(acf_error("unbalanced braces")),
+    }
+}
+
+fn parse_object(tokens: &mut impl Iterator<Item = (char, String)>) -> Result<Vec<(String, KeyValue)>> {
+    let mut object = Vec::new();
+    while let Some((kind, key)) = tokens.next() {
+        match kind {
+            '}' => return Ok(object),
+            '"' => {}
+            _ => return Err(acf_error("expected key")),
+        }
+        match tokens.next() {
+            Some(('"', value)) => object.push((key, KeyValue::Value(value))),
+            Some(('{', _)) => object.push((key, KeyValue::Object(parse_object(tokens)?))),
+            _ => return Err(acf_error("expected value")),
+        }
+    }
+    Ok(object)
+}
+
+fn acf_error(message: &str) -> LpkError {
+    LpkError::DecodeError {
+        format: "acf".to_string(),
+        path: String::new(),
+        stage: ErrorStage::Config,
+        message: message.to_string(),
+        source: None,
+    }
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).context(ErrorStage::Open, dir.display())? {
+        let path = entry.context(ErrorStage::Open, dir.display())?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)),
+        None => false,
+    }
+}
@@ -0,0 +1,38 @@
+//! 测试共用的模型和LPK生成函数, lpk-ffi 的测试也通过 `#[path]` 引用这里
+// 每个测试程序只用到其中一部分
+#![allow(dead_code)]
+
+use lpk::LpkWriter;
+use std::{io::Cursor, path::Path};
+use tempfile::TempDir;
+
+/// 在临时目录中生成一个最小的 Cubism 模型
+pub fn make_model(dir: &Path) {
+    std::fs::create_dir_all(dir.join("textures")).unwrap();
+    std::fs::write(dir.join("hiyori.moc3"), b"MOC3\x03\x00\x00\x00 model data").unwrap();
+    std::fs::write(dir.join("textures/texture_00.png"), b"\x89PNG\r\n\x1a\n texture data").unwrap();
+    std::fs::write(
+        dir.join("hiyori.model3.json"),
+        r#"{"Version":3,"FileReferences":{"Moc":"hiyori.moc3","Textures":["textures/texture_00.png"]}}"#,
+    )
+    .unwrap();
+}
+
+/// 生成最小模型并按 `(角色, 服装)` 打包为标准格式的LPK, 模型位于返回的临时目录下的 `model`
+pub fn packed_model(costumes: &[(&str, &str)]) -> (TempDir, Vec<u8>) {
+    packed_model_with(costumes, |_| {})
+}
+
+/// 同 [`packed_model`], 打包前由 `customize` 修改模型目录
+pub fn packed_model_with(costumes: &[(&str, &str)], customize: impl FnOnce(&Path)) -> (TempDir, Vec<u8>) {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+    customize(&model_dir);
+    let mut writer = LpkWriter::new("1234567890", "Hiyori");
+    for (character, costume) in costumes {
+        writer.add_costume(character, costume, &model_dir).unwrap();
+    }
+    let bytes = writer.write(Cursor::new(Vec::new())).unwrap().into_inner();
+    (temp, bytes)
+}
@@ -1,7 +1,18 @@
-use lpk::LpkLoader;
-use std::path::Path;
+use lpk::{
+    helpers::{decrypt, decrypt_in_place, make_key},
+    AssetLayout, CancellationToken, EncryptMode, EntryKind, ErrorStage, ExtractEvent, ExtractSink, FileType, KeyScheme,
+    Keystream, LpkConfig, LpkError, LpkLoader, LpkType, LpkWriter, MLveConfig, MemorySink, Selection, TarCompression, TarSink,
+    WorkshopItem, WorkshopManifest, ZipSink, INCREMENTAL_MANIFEST, SHARED_ASSETS_DIR, WORKSHOP_APP_ID,
+};
+use std::{
+    io::{Cursor, Read, Write},
+    path::Path,
+};
 use tracing::metadata::LevelFilter;
 
+mod common;
+use common::{make_model, packed_model, packed_model_with};
+
 #[test]
 fn ready() {
     println!("it works!")
@@ -25,3 +36,1203 @@ fn test_lpk_loader() {
 
     println!("Successfully extracted LPK file to: {}", output_dir.path().display());
 }
+
+#[test]
+fn test_lpk_writer_round_trip() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+
+    let mut writer = LpkWriter::new("1234567890", "Hiyori");
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let lpk_path = temp.path().join("hiyori.lpk");
+    writer.save(&lpk_path).unwrap();
+
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::open(&lpk_path).unwrap();
+    loader.extract(&output).unwrap();
+
+    let json = std::fs::read_to_string(output.join("hiyori/hiyori-default.model3.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let moc = json["FileReferences"]["Moc"].as_str().unwrap();
+    let texture = json["FileReferences"]["Textures"][0].as_str().unwrap();
+    assert_eq!(moc, "model.moc3");
+    assert_eq!(std::fs::read(output.join("hiyori").join(moc)).unwrap(), std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+    assert_eq!(
+        std::fs::read(output.join("hiyori").join(texture)).unwrap(),
+        std::fs::read(model_dir.join("textures/texture_00.png")).unwrap()
+    );
+}
+
+#[test]
+fn test_lpk_writer_steam_round_trip() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+
+    let config = LpkConfig {
+        file_id: "1363062649".to_string(),
+        meta_data: "e6fec759-9ce3-453d-aba5-83162c304b42".to_string(),
+        ..LpkConfig::default()
+    };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config);
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let lpk_path = temp.path().join("1363062649.lpk");
+    writer.save(&lpk_path).unwrap();
+    assert!(temp.path().join("config.json").exists());
+
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::open(&lpk_path).unwrap();
+    loader.extract(&output).unwrap();
+
+    let json = std::fs::read_to_string(output.join("hiyori/hiyori-default.model3.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let moc = json["FileReferences"]["Moc"].as_str().unwrap();
+    assert_eq!(std::fs::read(output.join("hiyori").join(moc)).unwrap(), std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+}
+
+#[test]
+fn test_lpk_loader_from_reader() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    loader.extract(&output).unwrap();
+    assert!(output.join("hiyori/hiyori-default.model3.json").exists());
+}
+
+#[test]
+fn test_lpk_loader_from_reader_with_config() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+
+    let config = LpkConfig {
+        file_id: "1363062649".to_string(),
+        meta_data: "e6fec759-9ce3-453d-aba5-83162c304b42".to_string(),
+        ..LpkConfig::default()
+    };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config);
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let bytes = writer.write(Cursor::new(Vec::new())).unwrap().into_inner();
+
+    assert!(matches!(LpkLoader::from_reader(Cursor::new(bytes.clone())), Err(LpkError::ConfigMissing)));
+
+    let config = br#"{"lpkFile":"","file":"","previewFile":"","fileId":"1363062649","type":0,"stereoMode":0,"title":"","author":"","description":"","metaData":"e6fec759-9ce3-453d-aba5-83162c304b42"}"#;
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::from_reader_with_config_bytes(Cursor::new(bytes), config).unwrap();
+    loader.extract(&output).unwrap();
+
+    let json = std::fs::read_to_string(output.join("hiyori/hiyori-default.model3.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let moc = json["FileReferences"]["Moc"].as_str().unwrap();
+    assert_eq!(std::fs::read(output.join("hiyori").join(moc)).unwrap(), std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+}
+
+#[test]
+fn test_lpk_loader_entries() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+    let model_dir = temp.path().join("model");
+
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    let entries = loader.entries().unwrap();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries.iter().filter(|e| e.kind == EntryKind::Config).count(), 1);
+
+    let model = entries.iter().find(|e| e.kind == EntryKind::Model).unwrap();
+    assert_eq!(model.character.as_deref(), Some("hiyori"));
+    assert_eq!(model.costume.as_deref(), Some("default"));
+
+    let mut json = String::new();
+    loader.open_entry(&model.name).unwrap().read_to_string(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let moc = json["FileReferences"]["Moc"].as_str().unwrap();
+    assert_eq!(loader.read_entry(moc).unwrap(), std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+
+    // 资源条目从模型 JSON 的引用得到所属角色、服装和可读文件名
+    let moc = entries.iter().find(|e| e.name == moc).unwrap();
+    assert_eq!(moc.kind, EntryKind::Asset);
+    assert_eq!(moc.character.as_deref(), Some("hiyori"));
+    assert_eq!(moc.costume.as_deref(), Some("default"));
+    assert_eq!(moc.original_name.as_deref(), Some("model.moc3"));
+    let texture = entries.iter().find(|e| e.original_name.as_deref() == Some("textures/texture_00.png")).unwrap();
+    assert_eq!(texture.costume.as_deref(), Some("default"));
+}
+
+#[test]
+fn test_entries_tolerate_undecryptable_entries() {
+    let temp = tempfile::tempdir().unwrap();
+    make_model(temp.path());
+    let config = LpkConfig { file_id: "1363062649".to_string(), meta_data: "meta".to_string(), ..LpkConfig::default() };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config);
+    writer.add_costume("hiyori", "default", temp.path()).unwrap();
+    let mut zip = zip::ZipWriter::new_append(writer.write(Cursor::new(Vec::new())).unwrap()).unwrap();
+    zip.start_file("broken.bin", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.write_all(&(0..256u32).map(|i| (i.wrapping_mul(2654435761) >> 7) as u8).collect::<Vec<_>>()).unwrap();
+    let bytes = zip.finish().unwrap().into_inner();
+
+    // 无法恢复密钥的条目类型未知, 不影响其他条目
+    let mut loader = LpkLoader::from_reader_with_key_recovery(Cursor::new(bytes.clone())).unwrap();
+    let entries = loader.entries().unwrap();
+    assert_eq!(entries.len(), 5);
+    let broken = entries.iter().find(|e| e.name == "broken.bin").unwrap();
+    assert_eq!(broken.file_type, FileType::Unknown);
+    let mut types = entries
+        .iter()
+        .filter(|e| e.kind == EntryKind::Asset && e.name != "broken.bin")
+        .map(|e| e.file_type)
+        .collect::<Vec<_>>();
+    types.sort_by_key(|t| t.extension());
+    assert_eq!(types, [FileType::Moc3, FileType::Png]);
+    assert_eq!(entries.iter().find(|e| e.kind == EntryKind::Model).unwrap().file_type, FileType::Json);
+    assert!(matches!(loader.read_entry("broken.bin"), Err(LpkError::DecryptionFailed { .. })));
+
+    // 未知类型且探测不到密钥规则时仍然可以列出条目
+    let loader = LpkLoader::from_reader(Cursor::new(repack(bytes, "XYZ_9_9", &|_, data| data)));
+    let entries = loader.unwrap().entries().unwrap();
+    assert_eq!(entries.len(), 5);
+    assert!(entries.iter().filter(|e| e.kind != EntryKind::Config).all(|e| e.file_type == FileType::Unknown));
+}
+
+/// 逐字节计算的参考实现
+fn reference_decrypt(key: i128, data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(data.len());
+    for chunk in data.chunks(1024) {
+        let mut k = key;
+        for &byte in chunk {
+            k = (65535 & ((2531011 + 214013 * k) >> 16)) & 0xffffffff;
+            ret.push((k & 0xff) as u8 ^ byte);
+        }
+    }
+    ret
+}
+
+#[test]
+fn test_keystream_matches_reference() {
+    let data = (0..5000u32).map(|i| (i * 7 + 3) as u8).collect::<Vec<_>>();
+    for key in [0, make_key("1234567890a1b2c3.bin"), make_key("zzzzzzzzzzzzzzzz"), -1] {
+        assert_eq!(decrypt(key, &data), reference_decrypt(key, &data));
+
+        let mut in_place = data.clone();
+        decrypt_in_place(key, &mut in_place);
+        assert_eq!(in_place, reference_decrypt(key, &data));
+
+        // 从任意偏移开始流式解密
+        let keystream = Keystream::new(key);
+        let mut streamed = data.clone();
+        let (head, tail) = streamed.split_at_mut(1500);
+        keystream.decrypt_at(0, &mut head[..700]);
+        keystream.decrypt_at(700, &mut head[700..]);
+        keystream.decrypt_at(1500, tail);
+        assert_eq!(streamed, reference_decrypt(key, &data));
+    }
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn test_parallel_extract_matches_sequential() {
+    let (temp, bytes) = packed_model_with(&[("hiyori", "default")], |model_dir| {
+        for i in 0..64 {
+            std::fs::write(
+                model_dir.join(format!("motion_{i}.motion3.json")),
+                format!(r#"{{"Version":3,"Id":{i}}}"#).repeat(100),
+            )
+            .unwrap();
+        }
+    });
+
+    let sequential = temp.path().join("sequential");
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract(&sequential).unwrap();
+
+    let parallel = temp.path().join("parallel");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    loader.set_parallel(true);
+    loader.extract(&parallel).unwrap();
+
+    assert_eq!(read_tree(&sequential), read_tree(&parallel));
+}
+
+/// 递归读取目录中的所有文件, 键为相对路径
+fn read_tree(dir: &Path) -> std::collections::BTreeMap<std::path::PathBuf, Vec<u8>> {
+    let mut files = std::collections::BTreeMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(current).unwrap() {
+            let path = entry.unwrap().path();
+            match path.is_dir() {
+                true => pending.push(path),
+                false => {
+                    files.insert(path.strip_prefix(dir).unwrap().to_path_buf(), std::fs::read(&path).unwrap());
+                }
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn test_steam_key_recovery_without_config() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+
+    let config = LpkConfig {
+        file_id: "1363062649".to_string(),
+        meta_data: "e6fec759-9ce3-453d-aba5-83162c304b42".to_string(),
+        ..LpkConfig::default()
+    };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config);
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let lpk_path = temp.path().join("1363062649.lpk");
+    writer.save(&lpk_path).unwrap();
+    std::fs::remove_file(temp.path().join("config.json")).unwrap();
+
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::open(&lpk_path).unwrap();
+    loader.extract(&output).unwrap();
+
+    let json = std::fs::read_to_string(output.join("hiyori/hiyori-default.model3.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let moc = json["FileReferences"]["Moc"].as_str().unwrap();
+    let texture = json["FileReferences"]["Textures"][0].as_str().unwrap();
+    assert_eq!(std::fs::read(output.join("hiyori").join(moc)).unwrap(), std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+    assert_eq!(
+        std::fs::read(output.join("hiyori").join(texture)).unwrap(),
+        std::fs::read(model_dir.join("textures/texture_00.png")).unwrap()
+    );
+}
+
+#[test]
+fn test_key_recovery_of_many_json_entries() {
+    // 短文件头只有两三个字节的已知明文, 不能让 JSON 条目误中
+    for seed in (0..=u16::MAX).step_by(97) {
+        let plain = format!(r#"{{"Version":3,"Meta":{{"Duration":{seed}}},"Curves":[]}}"#);
+        let mut data = plain.clone().into_bytes();
+        Keystream::from_seed(seed).decrypt_in_place(&mut data);
+        assert_eq!(lpk::recovery::recover_keystream(&data).map(|k| k.seed()), Some(seed), "{plain}");
+    }
+
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+    let mut motions = Vec::new();
+    for i in 0..300 {
+        let motion = format!(r#"{{"Version":3,"Meta":{{"Duration":{i}.5,"Loop":true}},"Curves":[]}}"#).into_bytes();
+        std::fs::write(model_dir.join(format!("motion_{i}.motion3.json")), &motion).unwrap();
+        motions.push(motion);
+    }
+    let config = LpkConfig { file_id: "1363062649".to_string(), meta_data: "meta".to_string(), ..LpkConfig::default() };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config);
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let bytes = writer.write(Cursor::new(Vec::new())).unwrap().into_inner();
+
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::from_reader_with_key_recovery(Cursor::new(bytes)).unwrap();
+    loader.extract(&output).unwrap();
+    let written = read_tree(&output).into_values().collect::<Vec<_>>();
+    assert!(motions.iter().all(|motion| written.contains(motion)));
+}
+
+#[test]
+fn test_file_type_sniffing() {
+    assert_eq!(FileType::sniff(b"\x89PNG\r\n\x1a\n\0\0"), FileType::Png);
+    assert_eq!(FileType::sniff(b"MOC3\x03"), FileType::Moc3);
+    assert_eq!(FileType::sniff(b"moc\x0b"), FileType::Moc);
+    assert_eq!(FileType::sniff(b"\xEF\xBB\xBF\r\n {\"Version\": 3}"), FileType::Json);
+    assert_eq!(FileType::sniff(b"OggS\0\x02"), FileType::Ogg);
+    assert_eq!(FileType::sniff(b"RIFF\x24\0\0\0WAVEfmt "), FileType::Wav);
+    assert_eq!(FileType::sniff(b"ID3\x04\0"), FileType::Mp3);
+    assert_eq!(FileType::sniff(b"\xFF\xFB\x90\x64"), FileType::Mp3);
+    assert_eq!(FileType::sniff(b"\x01\x02\x03"), FileType::Unknown);
+    assert_eq!(FileType::sniff(b"\xFF\xD8\xFF\xE0\0\x10JFIF"), FileType::Jpeg);
+    assert_eq!(FileType::sniff(b"RIFF\x24\0\0\0WEBPVP8 "), FileType::Webp);
+    assert_eq!(FileType::sniff(b"\0\0\0\x20ftypisom"), FileType::Mp4);
+    assert_eq!(FileType::sniff(b"\0\x01\0\0\0\x0e\0\x80"), FileType::Ttf);
+    assert_eq!(FileType::sniff(b"wOF2\0\x01\0\0"), FileType::Woff2);
+    // 比特率和采样率是保留值的帧头不是 MP3
+    assert_eq!(FileType::sniff(b"\xFF\xFF\xFF\xFF"), FileType::Unknown);
+
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+    let mut types = loader.entries().unwrap().into_iter().map(|e| e.file_type).collect::<Vec<_>>();
+    types.sort_by_key(|t| t.extension());
+    assert_eq!(types, [FileType::Json, FileType::Json, FileType::Moc3, FileType::Png]);
+
+    let output = temp.path().join("output");
+    loader.extract(&output).unwrap();
+    let json = std::fs::read_to_string(output.join("hiyori/hiyori-default.model3.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let moc = json["FileReferences"]["Moc"].as_str().unwrap();
+    let texture = json["FileReferences"]["Textures"][0].as_str().unwrap();
+    assert!(moc.ends_with(".moc3") && output.join("hiyori").join(moc).exists());
+    assert!(texture.ends_with(".png") && output.join("hiyori").join(texture).exists());
+
+    // 已有的扩展名即使无法识别也保持不变
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..archive.len() {
+        zip.raw_copy_file(archive.by_index(i).unwrap()).unwrap();
+    }
+    for (name, plain) in [("idle.mtn", b"# Live2D Animator Motion Data".as_slice()), ("readme.txt", b"hello")] {
+        zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&decrypt(make_key(&format!("1234567890{name}")), plain)).unwrap();
+    }
+    let bytes = zip.finish().unwrap().into_inner();
+    let output = temp.path().join("extensions");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    let planned = loader.plan(&output).unwrap().files.into_iter().map(|f| f.output).collect::<Vec<_>>();
+    loader.extract(&output).unwrap();
+    for name in ["idle.mtn", "readme.txt"] {
+        assert!(planned.contains(&output.join("hiyori").join(name)), "{name}");
+        assert!(output.join("hiyori").join(name).exists(), "{name}");
+    }
+}
+
+#[test]
+fn test_restore_readable_names() {
+    let (temp, bytes) = packed_model_with(&[("hiyori", "default")], |model_dir| {
+        std::fs::create_dir_all(model_dir.join("motions")).unwrap();
+        std::fs::write(model_dir.join("motions/idle.motion3.json"), r#"{"Version":3,"Curves":[]}"#).unwrap();
+        std::fs::write(model_dir.join("hiyori.physics3.json"), r#"{"Version":3}"#).unwrap();
+        std::fs::write(model_dir.join("smile.exp3.json"), r#"{"Type":"Live2D Expression"}"#).unwrap();
+        std::fs::write(
+            model_dir.join("hiyori.model3.json"),
+            r#"{"Version":3,"FileReferences":{"Moc":"hiyori.moc3","Textures":["textures/texture_00.png"],"Physics":"hiyori.physics3.json",
+            "Expressions":[{"Name":"../smile","File":"smile.exp3.json"}],"Motions":{"Idle":[{"File":"motions/idle.motion3.json"}]}}}"#,
+        )
+        .unwrap();
+    });
+    let model_dir = temp.path().join("model");
+
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    loader.extract(&output).unwrap();
+
+    let root = output.join("hiyori");
+    let json = std::fs::read_to_string(root.join("hiyori-default.model3.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let refs = &json["FileReferences"];
+    assert_eq!(refs["Moc"], "model.moc3");
+    assert_eq!(refs["Textures"][0], "textures/texture_00.png");
+    assert_eq!(refs["Physics"], "model.physics3.json");
+    assert_eq!(refs["Expressions"][0]["File"], "expressions/_smile.exp3.json");
+    assert_eq!(refs["Motions"]["Idle"][0]["File"], "motions/Idle_0.motion3.json");
+    assert_eq!(
+        std::fs::read(root.join("textures/texture_00.png")).unwrap(),
+        std::fs::read(model_dir.join("textures/texture_00.png")).unwrap()
+    );
+    assert!(root.join("motions/Idle_0.motion3.json").exists());
+
+    let mapping = std::fs::read_to_string(root.join("mapping.json")).unwrap();
+    let mapping: std::collections::BTreeMap<String, String> = serde_json::from_str(&mapping).unwrap();
+    assert!(mapping.values().any(|v| v == "model.moc3"));
+    assert!(mapping.keys().all(|k| lpk::helpers::is_encrypted_file(k)));
+}
+
+#[test]
+fn test_extraction_plan_matches_extract() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default"), ("hiyori", "summer")]);
+
+    let output = temp.path().join("output");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    let plan = loader.plan(&output).unwrap();
+    assert!(!output.exists());
+    assert!(plan.files.iter().all(|f| !f.overwrite));
+    assert!(plan.to_json().unwrap().contains("\"scheme\": \"Standard\""));
+
+    loader.extract(&output).unwrap();
+    let mut written = Vec::new();
+    let mut pending = vec![output.clone()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            match path.is_dir() {
+                true => pending.push(path),
+                false => written.push(path),
+            }
+        }
+    }
+    written.sort();
+    assert_eq!(plan.files.iter().map(|f| f.output.clone()).collect::<Vec<_>>(), written);
+    for file in &plan.files {
+        assert_eq!(file.size, std::fs::metadata(&file.output).unwrap().len(), "{}", file.output.display());
+    }
+    assert!(loader.plan(&output).unwrap().files.iter().all(|f| f.overwrite));
+}
+
+#[test]
+fn test_hostile_config_names_stay_inside_output() {
+    let (temp, bytes) = packed_model(&[("../../evil", "../../x"), ("", "con")]);
+
+    let output = temp.path().join("a").join("output");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    let plan = loader.plan(&output).unwrap();
+    assert!(plan.files.iter().all(|f| f.output.starts_with(&output)));
+    loader.extract(&output).unwrap();
+    assert_eq!(std::fs::read_dir(temp.path().join("a")).unwrap().count(), 1);
+    assert!(output.join("_.._evil").join("_.._evil-.._.._x.model3.json").exists());
+    assert!(output.join("character").join("-con.model3.json").exists());
+}
+
+#[test]
+fn test_hostile_entry_name_is_rejected() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+    for hostile in ["../escape.bin", "/absolute.bin", "..\\escape.bin"] {
+        let mut zip = zip::ZipWriter::new_append(Cursor::new(bytes.clone())).unwrap();
+        zip.start_file(hostile, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&decrypt(make_key(&format!("1234567890{hostile}")), b"owned")).unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let output = temp.path().join("output");
+        let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+        assert!(matches!(loader.extract(&output), Err(LpkError::UnsafePath(_))), "{hostile}");
+        assert!(!temp.path().join("escape.bin").exists());
+        assert!(!Path::new("/absolute.bin").exists());
+
+        // 旧版格式按压缩包中的条目名解压, 同样不能跳过或写出目录之外
+        let legacy = repack(bytes, "STD_1_0", &|_, data| data);
+        let output = temp.path().join("legacy");
+        let mut loader = LpkLoader::from_reader(Cursor::new(legacy)).unwrap();
+        assert!(matches!(loader.plan(&output), Err(LpkError::UnsafePath(_))), "{hostile}");
+        assert!(matches!(loader.extract(&output), Err(LpkError::UnsafePath(_))), "{hostile}");
+        assert!(!temp.path().join("escape.bin").exists());
+        assert!(!Path::new("/absolute.bin").exists());
+    }
+}
+
+#[test]
+fn test_errors_carry_stage_path_and_source() {
+    use std::error::Error;
+    let temp = tempfile::tempdir().unwrap();
+    let missing = temp.path().join("missing.lpk");
+    let error = LpkLoader::open(&missing).err().unwrap();
+    assert_eq!(error.code(), "io");
+    assert_eq!(error.stage(), ErrorStage::Open);
+    assert_eq!(error.path(), Some(missing.display().to_string().as_str()));
+    assert!(error.source().unwrap().downcast_ref::<std::io::Error>().is_some());
+    assert!(error.english().starts_with("IO error during open"));
+
+    let garbage = temp.path().join("garbage.lpk");
+    std::fs::write(&garbage, b"not a zip").unwrap();
+    let error = LpkLoader::open(&garbage).err().unwrap();
+    assert_eq!((error.code(), error.stage()), ("zip", ErrorStage::Open));
+    assert_eq!(error.path(), Some(garbage.display().to_string().as_str()));
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("config.mlve", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.write_all(b"{ broken").unwrap();
+    let bytes = zip.finish().unwrap().into_inner();
+    let error = LpkLoader::from_reader(Cursor::new(bytes)).err().unwrap();
+    assert_eq!((error.code(), error.stage(), error.path()), ("decode", ErrorStage::Config, Some("config.mlve")));
+    assert!(error.source().unwrap().downcast_ref::<serde_json::Error>().is_some());
+    assert!(error.to_string().starts_with("解码错误(配置)"));
+}
+
+#[test]
+fn test_extract_events() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    let sink = events.clone();
+    loader.set_observer(move |event: &ExtractEvent| sink.lock().unwrap().push(event.clone()));
+    let output = temp.path().join("output");
+    loader.extract(&output).unwrap();
+
+    let events = events.lock().unwrap();
+    assert!(matches!(events.first(), Some(ExtractEvent::Opened { lpk_type, entries: 4 }) if *lpk_type == LpkType::Std2_0));
+    assert!(matches!(events.last(), Some(ExtractEvent::Finished { output: o }) if o == &output));
+    assert!(events.iter().any(
+        |e| matches!(e, ExtractEvent::CharacterStarted { character, costume } if character == "hiyori" && costume == "default")
+    ));
+    let decrypted = events.iter().filter(|e| matches!(e, ExtractEvent::EntryDecrypted { total: 4, .. })).count();
+    assert_eq!(decrypted, 4);
+    for event in events.iter() {
+        if let ExtractEvent::FileWritten { path, bytes } = event {
+            assert!(path.starts_with(&output));
+            // 写出后可能被重命名为可读文件名, 只检查模型 JSON 和映射
+            if path.extension().is_some_and(|e| e == "json") && path.exists() {
+                assert_eq!(*bytes, std::fs::metadata(path).unwrap().len());
+            }
+        }
+    }
+    assert!(events.iter().any(|e| matches!(e, ExtractEvent::FileWritten { path, .. } if path.ends_with("mapping.json"))));
+}
+
+#[test]
+fn test_cancel_extraction_removes_written_files() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default"), ("hiyori", "summer")]);
+
+    // 写出第一个文件后取消
+    let token = CancellationToken::new();
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+    loader.set_cancellation(token.clone());
+    let observer = token.clone();
+    loader.set_observer(move |event: &ExtractEvent| {
+        if let ExtractEvent::FileWritten { .. } = event {
+            observer.cancel();
+        }
+    });
+    let output = temp.path().join("output");
+    assert!(matches!(loader.extract(&output), Err(LpkError::Cancelled)));
+    assert!(read_tree(&output).is_empty());
+
+    // 取消后的令牌会让解压立即停止
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+    loader.set_cancellation(token);
+    let error = loader.extract(&output).unwrap_err();
+    assert_eq!(error.code(), "cancelled");
+    assert!(read_tree(&output).is_empty());
+
+    // 取消重新解压时保留之前已经存在的文件, 被覆盖的文件保持新的内容
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract(&output).unwrap();
+    let complete = read_tree(&output).into_keys().collect::<Vec<_>>();
+    for after in 1..complete.len() {
+        let token = CancellationToken::new();
+        let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+        loader.set_cancellation(token.clone());
+        let written = std::sync::atomic::AtomicUsize::new(0);
+        loader.set_observer(move |event: &ExtractEvent| {
+            if let ExtractEvent::FileWritten { .. } = event {
+                if written.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1 == after {
+                    token.cancel();
+                }
+            }
+        });
+        assert!(matches!(loader.extract(&output), Err(LpkError::Cancelled)));
+        assert_eq!(read_tree(&output).into_keys().collect::<Vec<_>>(), complete, "cancelled after {after} files");
+    }
+}
+
+#[test]
+fn test_wrong_key_is_reported() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+    let config = LpkConfig { file_id: "1363062649".to_string(), meta_data: "right".to_string(), ..LpkConfig::default() };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config.clone());
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let bytes = writer.write(Cursor::new(Vec::new())).unwrap().into_inner();
+
+    let wrong = LpkConfig { meta_data: "wrong".to_string(), ..config };
+    let mut loader = LpkLoader::from_reader_with_config(Cursor::new(bytes), wrong).unwrap();
+    let model = loader.mlve_config().list[0].costume[0].path.clone();
+    match loader.read_entry(&model) {
+        Err(LpkError::DecryptionFailed { entry, scheme }) => assert_eq!((entry, scheme), (model.clone(), KeyScheme::Steam)),
+        other => panic!("unexpected {:?}", other.map(|v| v.len())),
+    }
+    let error = loader.extract(&temp.path().join("output")).unwrap_err();
+    assert_eq!((error.code(), error.stage()), ("decryption_failed", ErrorStage::Decrypt));
+    assert!(error.english().contains("scheme Steam"));
+}
+
+#[test]
+fn test_unrecognized_assets_are_extracted() {
+    let jpeg = b"\xFF\xD8\xFF\xE0\0\x10JFIF\0\x01\x01 background".to_vec();
+    let voice = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8 | 0x80).collect::<Vec<_>>();
+    assert_eq!(FileType::sniff(&voice), FileType::Unknown);
+    let (temp, bytes) = packed_model_with(&[("hiyori", "default")], |model_dir| {
+        std::fs::write(model_dir.join("textures/bg.jpg"), &jpeg).unwrap();
+        std::fs::write(model_dir.join("voice.dat"), &voice).unwrap();
+        std::fs::write(
+            model_dir.join("hiyori.model3.json"),
+            r#"{"Version":3,"FileReferences":{"Moc":"hiyori.moc3","Textures":["textures/texture_00.png","textures/bg.jpg"],
+            "Motions":{"Idle":[{"File":"","Sound":"voice.dat"}]}}}"#,
+        )
+        .unwrap();
+    });
+
+    // 密钥正确时无法识别的二进制只发出警告, 计划与实际写出的文件一致
+    let warnings = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = warnings.clone();
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    loader.set_observer(move |event: &ExtractEvent| {
+        if let ExtractEvent::Warning { message } = event {
+            sink.lock().unwrap().push(message.clone());
+        }
+    });
+    let output = temp.path().join("output");
+    let plan = loader.plan(&output).unwrap();
+    loader.extract(&output).unwrap();
+    let tree = read_tree(&output);
+    assert_eq!(
+        plan.files.iter().map(|f| f.output.strip_prefix(&output).unwrap().to_path_buf()).collect::<Vec<_>>(),
+        tree.keys().cloned().collect::<Vec<_>>()
+    );
+    assert_eq!(tree[Path::new("hiyori/textures/texture_01.jpg")], jpeg);
+    assert_eq!(tree[Path::new("hiyori/sounds/Idle_0.bin")], voice);
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("Unrecognized content"));
+
+    let voice_entry = loader.entries().unwrap().into_iter().find(|e| e.original_name.as_deref() == Some("sounds/Idle_0.bin"));
+    assert_eq!(loader.read_entry(&voice_entry.unwrap().name).unwrap(), voice);
+}
+
+/// 由条目名和原始内容生成新的条目内容
+type Rekey<'a> = &'a dyn Fn(&str, Vec<u8>) -> Vec<u8>;
+
+/// 修改包类型并用 `rekey` 重新加密所有条目
+fn repack(bytes: Vec<u8>, lpk_type: &str, rekey: Rekey) -> Vec<u8> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).unwrap();
+        let name = file.name().to_string();
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        let data = match name == lpk::helpers::hashed_filename("config.mlve") {
+            true => {
+                let mut config: serde_json::Value = serde_json::from_slice(&data).unwrap();
+                config["type"] = lpk_type.into();
+                serde_json::to_vec(&config).unwrap()
+            }
+            false => rekey(&name, data),
+        };
+        zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&data).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_detect_key_scheme_of_unknown_type() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+    let model_dir = temp.path().join("model");
+
+    let standard = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().info();
+    assert_eq!(
+        (standard.lpk_type.as_str(), standard.key_scheme, standard.detected),
+        ("STD2_0", Some(KeyScheme::Standard), false)
+    );
+
+    let plain = |name: &str, data: Vec<u8>| decrypt(make_key(&format!("1234567890{name}")), &data);
+    let cases: [(KeyScheme, Rekey); 3] = [
+        (KeyScheme::Standard, &|_, data| data),
+        (KeyScheme::Zero, &|name, data| decrypt(0, &plain(name, data))),
+        (KeyScheme::Plain, &plain),
+    ];
+    for (scheme, rekey) in cases {
+        let bytes = repack(bytes.clone(), "XYZ_9_9", rekey);
+        let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+        let info = loader.info();
+        assert_eq!((info.key_scheme, info.detected), (Some(scheme), true), "{scheme:?}");
+        let output = temp.path().join(format!("{scheme:?}"));
+        loader.extract(&output).unwrap();
+        let moc = std::fs::read(output.join("hiyori").join("model.moc3")).unwrap();
+        assert_eq!(moc, std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+    }
+
+    // 无法探测时保持原来的错误
+    let garbage = repack(bytes, "XYZ_9_9", &|name, data| decrypt(make_key(name), &plain(name, data)));
+    let loader = LpkLoader::from_reader(Cursor::new(garbage)).unwrap();
+    assert_eq!((loader.info().key_scheme, loader.info().detected), (None, false));
+
+    // Steam 密钥需要同目录的 config.json
+    let config = LpkConfig { file_id: "1363062649".to_string(), meta_data: "meta".to_string(), ..LpkConfig::default() };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config.clone());
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let bytes = repack(writer.write(Cursor::new(Vec::new())).unwrap().into_inner(), "XYZ_9_9", &|_, data| data);
+    let steam = temp.path().join("steam");
+    std::fs::create_dir_all(&steam).unwrap();
+    std::fs::write(steam.join("steam.lpk"), &bytes).unwrap();
+    std::fs::write(steam.join("config.json"), serde_json::to_vec(&config).unwrap()).unwrap();
+    let mut loader = LpkLoader::open(&steam.join("steam.lpk")).unwrap();
+    assert_eq!((loader.info().key_scheme, loader.info().detected), (Some(KeyScheme::Steam), true));
+    loader.extract(&steam.join("output")).unwrap();
+    let moc = std::fs::read(steam.join("output/hiyori/model.moc3")).unwrap();
+    assert_eq!(moc, std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+    let loader = LpkLoader::from_reader_with_config(Cursor::new(bytes), config).unwrap();
+    assert_eq!(loader.info().key_scheme, Some(KeyScheme::Steam));
+}
+
+#[test]
+fn test_lpk_type_and_encrypt_mode() {
+    for (text, lpk_type) in [("STD_1_0", LpkType::Std1_0), ("STD2_0", LpkType::Std2_0), ("STM_1_0", LpkType::Stm1_0)] {
+        assert_eq!(LpkType::from(text), lpk_type);
+        assert_eq!(lpk_type.to_string(), text);
+    }
+    assert_eq!(LpkType::from("STX_3_0"), LpkType::Unknown("STX_3_0".to_string()));
+    assert!(LpkType::Stm1_0.is_standard() && !LpkType::Std1_0.is_standard());
+
+    let json = r#"{"type":"STX_3_0","name":"n","id":"1","encrypt":"maybe","version":"3.0","list":[]}"#;
+    let config: lpk::MLveConfig = serde_json::from_str(json).unwrap();
+    assert_eq!(config.r#type, LpkType::Unknown("STX_3_0".to_string()));
+    assert_eq!(config.encrypt, EncryptMode::Unknown("maybe".to_string()));
+    assert_eq!(serde_json::to_string(&config).unwrap(), json);
+
+    let writer = LpkWriter::new("1234567890", "Hiyori");
+    assert_eq!((&writer.mlve_config().r#type, &writer.mlve_config().encrypt), (&LpkType::Std2_0, &EncryptMode::Enabled));
+}
+
+#[test]
+fn test_configs_round_trip() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/configs");
+    for (file, lpk_type) in
+        [("std_1_0.mlve", LpkType::Std1_0), ("std2_0.mlve", LpkType::Std2_0), ("stm_1_0.mlve", LpkType::Stm1_0)]
+    {
+        let bytes = std::fs::read(dir.join(file)).unwrap();
+        let config = MLveConfig::from_bytes(&bytes).unwrap();
+        assert_eq!(config.r#type, lpk_type);
+        assert_eq!(serde_json::to_string(&config).unwrap(), String::from_utf8(bytes).unwrap().trim_end());
+    }
+    let config = MLveConfig::from_bytes(&std::fs::read(dir.join("std2_0.mlve")).unwrap()).unwrap();
+    assert_eq!(config.extra["background"], "e1f2a3.bin");
+    assert_eq!(config.list[0].extra["scale"], 1.25);
+    assert_eq!(config.list[0].costume[0].extra["voice"], true);
+
+    let config = MLveConfig::from_bytes(&std::fs::read(dir.join("minimal.mlve")).unwrap()).unwrap();
+    assert_eq!((config.id.as_str(), &config.encrypt), ("", &EncryptMode::default()));
+    assert_eq!((config.list[0].avatar.as_str(), config.list[0].costume[0].name.as_str()), ("", ""));
+
+    let json = std::fs::read_to_string("tests/1363062649/config.json").unwrap();
+    let config = LpkConfig::from_bytes(json.as_bytes()).unwrap();
+    assert_eq!((config.file_id.as_str(), config.key.as_deref()), ("1363062649", None));
+    assert_eq!(serde_json::to_string(&config).unwrap(), json.trim_end());
+
+    // 显式的 null 和缺少 key 需要区分, 设置 key 后只写出一次
+    let json = json.trim_end().strip_suffix('}').unwrap().to_string() + r#","key":null}"#;
+    let mut config = LpkConfig::from_bytes(json.as_bytes()).unwrap();
+    assert_eq!(config.key, None);
+    assert_eq!(serde_json::to_string(&config).unwrap(), json);
+    config.key = Some("secret".to_string());
+    let written = serde_json::to_string(&config).unwrap();
+    assert_eq!(written.matches(r#""key""#).count(), 1);
+    assert_eq!(LpkConfig::from_bytes(written.as_bytes()).unwrap().key.as_deref(), Some("secret"));
+}
+
+#[test]
+fn test_workshop_item() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+    let workshop = temp.path().join("steamapps/workshop");
+    let item_dir = workshop.join("content").join(WORKSHOP_APP_ID).join("1363062649");
+    std::fs::create_dir_all(&item_dir).unwrap();
+
+    let config = LpkConfig {
+        file_id: "1363062649".to_string(),
+        meta_data: "e6fec759-9ce3-453d-aba5-83162c304b42".to_string(),
+        title: "Hiyori".to_string(),
+        preview_file: "preview.png".to_string(),
+        ..LpkConfig::default()
+    };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config);
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    writer.save(&item_dir.join("hiyori.lpk")).unwrap();
+    std::fs::write(item_dir.join("preview.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+    std::fs::write(
+        workshop.join("appworkshop_616720.acf"),
+        r#""AppWorkshop"
+{
+	"appid"		"616720"
+	// installed items
+	"WorkshopItemsInstalled"
+	{
+		"1363062649"
+		{
+			"size"		"5025795"
+			"timeupdated"		"1538015590"
+			"manifest"		"8520227218405567372"
+		}
+	}
+	"WorkshopItemDetails"
+	{
+		"1363062649"
+		{
+			"timeupdated"		"1538015590"
+			"timetouched"		"1700000000"
+			"subscribedby"		"76561198000000000"
+		}
+	}
+}
+"#,
+    )
+    .unwrap();
+
+    let item = WorkshopItem::open(&item_dir).unwrap();
+    assert_eq!((item.id.as_str(), item.title()), ("1363062649", Some("Hiyori")));
+    assert_eq!(item.lpk_path, item_dir.join("hiyori.lpk"));
+    assert_eq!(item.preview_path, Some(item_dir.join("preview.png")));
+    let manifest = item.manifest.clone().unwrap();
+    assert_eq!(
+        (manifest.size, manifest.time_updated, manifest.time_touched),
+        (Some(5025795), Some(1538015590), Some(1700000000))
+    );
+    assert_eq!(manifest.manifest.as_deref(), Some("8520227218405567372"));
+    assert_eq!(manifest.subscribed_by.as_deref(), Some("76561198000000000"));
+
+    let output = temp.path().join("output");
+    item.loader().unwrap().extract(&output).unwrap();
+    assert!(output.join("hiyori/hiyori-default.model3.json").exists());
+
+    // config.json 中指向物品目录之外的文件名被忽略
+    std::fs::write(item_dir.parent().unwrap().join("x.lpk"), b"outside").unwrap();
+    for hostile in ["../x.lpk", "/etc/passwd"] {
+        let config = serde_json::json!({ "lpkFile": hostile, "previewFile": hostile, "fileId": "1363062649" });
+        std::fs::write(item_dir.join("config.json"), config.to_string()).unwrap();
+        let item = WorkshopItem::open(&item_dir).unwrap();
+        assert_eq!(item.lpk_path, item_dir.join("hiyori.lpk"), "{hostile}");
+        assert_eq!(item.preview_path, Some(item_dir.join("preview.png")), "{hostile}");
+    }
+
+    std::fs::remove_file(item_dir.join("config.json")).unwrap();
+    let item = WorkshopItem::open(&item_dir).unwrap();
+    assert_eq!((item.config_path.is_none(), item.title()), (true, None));
+    assert_eq!(item.lpk_path, item_dir.join("hiyori.lpk"));
+    assert!(WorkshopItem::open(&model_dir).is_err());
+    assert!(WorkshopManifest::parse(r#""AppWorkshop" { "appid" "616720"#, "1").is_err());
+}
+
+#[test]
+fn test_extract_sinks() {
+    // 两个服装共用的文件只写出一次, 归档类的目标不允许重复写入
+    let (temp, bytes) = packed_model(&[("hiyori", "default"), ("hiyori", "summer")]);
+    let output = temp.path().join("output");
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract(&output).unwrap();
+    let expected = read_tree(&output);
+
+    let memory = MemorySink::new();
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract_to(&memory).unwrap();
+    assert_eq!(memory.into_files().into_iter().collect::<std::collections::BTreeMap<_, _>>(), expected);
+
+    let zip = ZipSink::new(Cursor::new(Vec::new()));
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract_to(&zip).unwrap();
+    let mut archive = zip::ZipArchive::new(zip.into_inner().unwrap()).unwrap();
+    assert_eq!(archive.len(), expected.len());
+    for (path, data) in &expected {
+        let mut file = archive.by_name(path.to_str().unwrap()).unwrap();
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).unwrap();
+        assert_eq!(&buffer, data);
+    }
+
+    for (name, compression) in [
+        ("hiyori.tar", TarCompression::None),
+        ("hiyori.tar.gz", TarCompression::Gzip),
+        ("hiyori.tar.zst", TarCompression::Zstd),
+    ] {
+        assert_eq!(TarCompression::from_path(Path::new(name)), compression);
+        let path = temp.path().join(name);
+        let tar = TarSink::create(&path).unwrap();
+        LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract_to(&tar).unwrap();
+        drop(tar);
+        let file = std::fs::File::open(&path).unwrap();
+        let reader: Box<dyn Read> = match compression {
+            TarCompression::None => Box::new(file),
+            TarCompression::Gzip => Box::new(flate2::read::GzDecoder::new(file)),
+            TarCompression::Zstd => Box::new(zstd::Decoder::new(file).unwrap()),
+        };
+        let mut files = std::collections::BTreeMap::new();
+        for entry in tar::Archive::new(reader).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            files.insert(entry.path().unwrap().to_path_buf(), buffer);
+        }
+        assert_eq!(files, expected);
+    }
+
+    // 解压失败时不留下不完整的压缩包
+    let token = CancellationToken::new();
+    token.cancel();
+    for name in ["cancelled.zip", "cancelled.tar.gz"] {
+        let path = temp.path().join(name);
+        let sink: Box<dyn ExtractSink> = match name.ends_with(".zip") {
+            true => Box::new(ZipSink::create(&path).unwrap()),
+            false => Box::new(TarSink::create(&path).unwrap()),
+        };
+        let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+        loader.set_cancellation(token.clone());
+        assert!(matches!(loader.extract_to(sink.as_ref()), Err(LpkError::Cancelled)));
+        drop(sink);
+        assert!(!path.exists(), "{name}");
+    }
+}
+
+#[test]
+fn test_extract_large_entries_in_chunks() {
+    // 超过分块解密阈值的纹理, 内容不可压缩
+    let mut texture = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    while texture.len() < 3 * 1024 * 1024 + 123 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        texture.push(state as u8);
+    }
+    let (temp, bytes) = packed_model_with(&[("hiyori", "default")], |model_dir| {
+        std::fs::write(model_dir.join("textures/texture_00.png"), &texture).unwrap()
+    });
+
+    let output = temp.path().join("output");
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract(&output).unwrap();
+    assert_eq!(std::fs::read(output.join("hiyori/textures/texture_00.png")).unwrap(), texture);
+    let expected = read_tree(&output);
+
+    let memory = MemorySink::new();
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract_to(&memory).unwrap();
+    assert_eq!(memory.into_files().into_iter().collect::<std::collections::BTreeMap<_, _>>(), expected);
+
+    let tar = TarSink::new(Cursor::new(Vec::new()), TarCompression::Gzip).unwrap();
+    LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap().extract_to(&tar).unwrap();
+    let data = tar.into_inner().unwrap().into_inner();
+    let mut files = std::collections::BTreeMap::new();
+    for entry in tar::Archive::new(flate2::read::GzDecoder::new(data.as_slice())).entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut buffer = Vec::new();
+        entry.read_to_end(&mut buffer).unwrap();
+        files.insert(entry.path().unwrap().to_path_buf(), buffer);
+    }
+    assert_eq!(files, expected);
+
+    // 旧版格式的未加密条目同样分块写出
+    let legacy = repack(bytes.clone(), "STD_1_0", &|name, data| decrypt(make_key(&format!("1234567890{name}")), &data));
+    let output = temp.path().join("legacy");
+    LpkLoader::from_reader(Cursor::new(legacy)).unwrap().extract(&output).unwrap();
+    assert!(read_tree(&output).values().any(|data| data == &texture));
+
+    // 分块写入过程中取消时不留下写了一半的文件
+    let token = CancellationToken::new();
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    loader.set_cancellation(token.clone());
+    loader.set_observer(move |event: &ExtractEvent| {
+        if let ExtractEvent::EntryDecrypted { bytes, .. } = event {
+            if *bytes > 1024 * 1024 {
+                token.cancel();
+            }
+        }
+    });
+    let output = temp.path().join("cancelled");
+    assert!(matches!(loader.extract(&output), Err(LpkError::Cancelled)));
+    assert!(read_tree(&output).is_empty());
+}
+
+#[test]
+fn test_incremental_extraction() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+    let model_dir = temp.path().join("model");
+    let output = temp.path().join("output");
+
+    let extract = |bytes: &[u8]| {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let mut loader = LpkLoader::from_reader(Cursor::new(bytes.to_vec())).unwrap();
+        loader.set_incremental(true);
+        loader.set_observer(move |event: &ExtractEvent| sink.lock().unwrap().push(event.clone()));
+        let plan = loader.plan(&output).unwrap();
+        loader.extract(&output).unwrap();
+        let events = events.lock().unwrap();
+        // 计划只列出实际写出的文件和记录文件
+        let mut written = events
+            .iter()
+            .filter_map(|e| match e {
+                ExtractEvent::FileWritten { path, .. } => Some(path.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        written.push(output.join(INCREMENTAL_MANIFEST));
+        written.sort();
+        assert_eq!(plan.files.iter().map(|f| f.output.clone()).collect::<Vec<_>>(), written);
+        for file in &plan.files {
+            assert_eq!(file.size, std::fs::metadata(&file.output).unwrap().len(), "{}", file.output.display());
+        }
+        let decrypted = events.iter().filter(|e| matches!(e, ExtractEvent::EntryDecrypted { .. })).count();
+        let skipped = events.iter().filter(|e| matches!(e, ExtractEvent::Skipped { .. })).count();
+        (decrypted, skipped)
+    };
+    assert_eq!(extract(&bytes), (4, 0));
+    assert!(output.join(INCREMENTAL_MANIFEST).exists());
+    let expected = read_tree(&output);
+    assert_eq!(extract(&bytes), (0, 4));
+    assert_eq!(read_tree(&output), expected);
+
+    // 被修改的输出文件会重新解压
+    std::fs::write(output.join("hiyori/model.moc3"), b"changed").unwrap();
+    assert_eq!(extract(&bytes), (1, 3));
+    assert_eq!(read_tree(&output), expected);
+
+    // 来源条目变化时只重新解压变化的条目
+    std::fs::write(model_dir.join("hiyori.moc3"), b"MOC3\x03\x00\x00\x00 updated model").unwrap();
+    let mut writer = LpkWriter::new("1234567890", "Hiyori");
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let updated = writer.write(Cursor::new(Vec::new())).unwrap().into_inner();
+    assert_eq!(extract(&updated), (1, 3));
+    assert_eq!(std::fs::read(output.join("hiyori/model.moc3")).unwrap(), std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+}
+
+#[test]
+fn test_incremental_extraction_of_legacy_pack() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default")]);
+    let bytes = repack(bytes, "STD_1_0", &|_, data| data);
+
+    // 旧版格式不支持增量解压, 发出警告后完整解压且不写出记录
+    let output = temp.path().join("output");
+    let warnings = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = warnings.clone();
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    loader.set_incremental(true);
+    loader.set_observer(move |event: &ExtractEvent| {
+        if let ExtractEvent::Warning { message } = event {
+            sink.lock().unwrap().push(message.clone());
+        }
+    });
+    let plan = loader.plan(&output).unwrap();
+    assert!(plan.files.iter().all(|f| !f.output.ends_with(INCREMENTAL_MANIFEST)));
+    loader.extract(&output).unwrap();
+    assert!(warnings.lock().unwrap().iter().any(|m| m.contains("Incremental extraction is not supported")));
+    assert!(!output.join(INCREMENTAL_MANIFEST).exists());
+}
+
+#[test]
+fn test_shared_asset_layouts() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default"), ("mao", "default")]);
+    let model_dir = temp.path().join("model");
+    let extract = |layout: AssetLayout, name: &str| {
+        let output = temp.path().join(name);
+        let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+        loader.set_asset_layout(layout);
+        let plan = loader.plan(&output).unwrap();
+        loader.extract(&output).unwrap();
+        // 计划与实际写出的文件一致, 共享资源每份内容只写出一次
+        let tree = read_tree(&output);
+        assert_eq!(
+            plan.files.iter().map(|f| f.output.strip_prefix(&output).unwrap()).collect::<Vec<_>>(),
+            tree.keys().collect::<Vec<_>>()
+        );
+        for file in &plan.files {
+            assert_eq!(file.size, tree[file.output.strip_prefix(&output).unwrap()].len() as u64, "{}", file.output.display());
+        }
+        output
+    };
+    let copy = read_tree(&extract(AssetLayout::Copy, "copy"));
+    assert!(copy.keys().all(|path| !path.starts_with(SHARED_ASSETS_DIR)));
+
+    // Windows 上创建符号链接需要额外的权限
+    let layouts = [(AssetLayout::Hardlink, "hardlink"), (AssetLayout::Symlink, "symlink")];
+    for (layout, name) in layouts.into_iter().filter(|(layout, _)| cfg!(unix) || *layout != AssetLayout::Symlink) {
+        let output = extract(layout, name);
+        let mut tree = read_tree(&output);
+        let shared = tree.keys().filter(|path| path.starts_with(SHARED_ASSETS_DIR)).cloned().collect::<Vec<_>>();
+        assert!(!shared.is_empty());
+        shared.iter().for_each(|path| drop(tree.remove(path)));
+        assert_eq!(tree, copy);
+        let moc = output.join("mao/model.moc3");
+        assert_eq!(std::fs::symlink_metadata(&moc).unwrap().file_type().is_symlink(), layout == AssetLayout::Symlink);
+    }
+
+    let output = extract(AssetLayout::SharedPaths, "shared");
+    let json = std::fs::read_to_string(output.join("mao/mao-default.model3.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let moc = json["FileReferences"]["Moc"].as_str().unwrap();
+    assert!(moc.starts_with("../_shared/"));
+    assert_eq!(std::fs::read(output.join("mao").join(moc)).unwrap(), std::fs::read(model_dir.join("hiyori.moc3")).unwrap());
+    assert!(!output.join("mao/model.moc3").exists() && !output.join("hiyori/model.moc3").exists());
+
+    // 内存中无法链接, 仍然各保存一份
+    let memory = MemorySink::new();
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+    loader.set_asset_layout(AssetLayout::Hardlink);
+    let warned = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let observer = warned.clone();
+    loader.set_observer(move |event: &ExtractEvent| {
+        if let ExtractEvent::Warning { .. } = event {
+            observer.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+    });
+    loader.extract_to(&memory).unwrap();
+    assert!(warned.load(std::sync::atomic::Ordering::Relaxed));
+    let files = memory.into_files();
+    assert!(copy.iter().all(|(path, data)| files.get(path) == Some(data)));
+}
+
+#[test]
+fn test_selective_extraction() {
+    let (temp, bytes) = packed_model(&[("hiyori", "default"), ("hiyori", "summer"), ("mao", "default")]);
+
+    let output = temp.path().join("summer");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+    loader.set_selection(Selection::all().costume("hiyori", "summer"));
+    let plan = loader.plan(&output).unwrap();
+    let decrypted = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = decrypted.clone();
+    loader.set_observer(move |event: &ExtractEvent| {
+        if let ExtractEvent::EntryDecrypted { .. } = event {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    });
+    loader.extract(&output).unwrap();
+    // 只解密模型和贴图两个被引用的条目
+    assert_eq!(decrypted.load(std::sync::atomic::Ordering::Relaxed), 2);
+    let tree = read_tree(&output);
+    let mut names = tree.keys().map(|p| p.to_string_lossy().replace('\\', "/")).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        ["hiyori/hiyori-summer.model3.json", "hiyori/mapping.json", "hiyori/model.moc3", "hiyori/textures/texture_00.png"]
+    );
+    assert_eq!(
+        plan.files.iter().map(|f| f.output.strip_prefix(&output).unwrap().to_path_buf()).collect::<Vec<_>>(),
+        tree.into_keys().collect::<Vec<_>>()
+    );
+
+    // 按角色 id 选择
+    let output = temp.path().join("mao");
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes.clone())).unwrap();
+    loader.set_selection(Selection::all().character("1"));
+    loader.extract(&output).unwrap();
+    assert!(output.join("mao/mao-default.model3.json").exists() && !output.join("hiyori").exists());
+
+    let mut loader = LpkLoader::from_reader(Cursor::new(bytes)).unwrap();
+    loader.set_selection(Selection::all().costume("hiyori", "winter"));
+    assert!(matches!(loader.extract(&temp.path().join("winter")), Err(LpkError::ModelMissing(s)) if s == "hiyori/winter"));
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn test_mmap_extract_matches_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+    let mut writer = LpkWriter::new("1234567890", "Hiyori");
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let lpk_path = temp.path().join("hiyori.lpk");
+    writer.save(&lpk_path).unwrap();
+
+    let file = temp.path().join("file");
+    LpkLoader::open(&lpk_path).unwrap().extract(&file).unwrap();
+    let mapped = temp.path().join("mmap");
+    let mut loader = LpkLoader::open_mmap(&lpk_path).unwrap();
+    assert_eq!(loader.mlve_config().name, "Hiyori");
+    loader.extract(&mapped).unwrap();
+    assert_eq!(read_tree(&file), read_tree(&mapped));
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_async_loader() {
+    let temp = tempfile::tempdir().unwrap();
+    let model_dir = temp.path().join("model");
+    make_model(&model_dir);
+    let config = LpkConfig { file_id: "1363062649".to_string(), meta_data: "meta".to_string(), ..LpkConfig::default() };
+    let mut writer = LpkWriter::steam("1234567890", "Hiyori", config.clone());
+    writer.add_costume("hiyori", "default", &model_dir).unwrap();
+    let lpk_path = temp.path().join("hiyori.lpk");
+    writer.save(&lpk_path).unwrap();
+    std::fs::write(temp.path().join("config.json"), serde_json::to_vec(&config).unwrap()).unwrap();
+
+    let loader = lpk::AsyncLpkLoader::open(&lpk_path).await.unwrap();
+    let model = loader.mlve_config().await.unwrap().list[0].costume[0].path.clone();
+    assert!(loader.entries().await.unwrap().iter().any(|e| e.name == model));
+    assert!(loader.read_entry(&model).await.unwrap().starts_with(b"{"));
+
+    let sync = temp.path().join("sync");
+    LpkLoader::open(&lpk_path).unwrap().extract(&sync).unwrap();
+    let output = temp.path().join("async");
+    loader.extract(&output).await.unwrap();
+    assert_eq!(read_tree(&sync), read_tree(&output));
+
+    let error = lpk::AsyncLpkLoader::open(temp.path().join("missing.lpk")).await.err().unwrap();
+    assert_eq!((error.code(), error.stage()), ("io", ErrorStage::Open));
+}
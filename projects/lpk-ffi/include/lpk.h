@@ -0,0 +1,77 @@
+#ifndef LPK_H
+#define LPK_H
+
+/* 由 cbindgen 生成, 不要手动修改. 更新: LPK_UPDATE_HEADER=1 cargo test -p lpk-ffi */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// 操作成功
+#define LPK_OK 0
+
+// 操作失败, 错误信息见 `lpk_last_error`
+#define LPK_ERROR -1
+
+// 不透明的LPK加载器句柄, 使用 `lpk_close` 释放
+typedef struct LpkHandle LpkHandle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// 打开LPK文件, Steam Workshop LPK 读取同目录的 config.json, 失败时返回 `NULL`
+struct LpkHandle *lpk_open(const char *path);
+
+// 打开LPK文件并使用指定的 config.json, 失败时返回 `NULL`
+struct LpkHandle *lpk_open_with_config(const char *path, const char *config_path);
+
+// 从内存打开LPK, 数据会被复制, 失败时返回 `NULL`
+//
+// `config` 是 config.json 的内容, 可以为 `NULL`, 此时 Steam Workshop LPK 从已知明文恢复密钥.
+struct LpkHandle *lpk_open_buffer(const uint8_t *data,
+                                  size_t len,
+                                  const uint8_t *config,
+                                  size_t config_len);
+
+// 释放句柄, `handle` 可以为 `NULL`
+void lpk_close(struct LpkHandle *handle);
+
+// 角色数量
+size_t lpk_character_count(const struct LpkHandle *handle);
+
+// 角色 id, 越界时返回 `NULL`, 字符串在句柄释放前有效
+const char *lpk_character_id(const struct LpkHandle *handle, size_t character);
+
+// 角色名称, 越界时返回 `NULL`, 字符串在句柄释放前有效
+const char *lpk_character_name(const struct LpkHandle *handle, size_t character);
+
+// 角色的服装数量, 越界时返回 0
+size_t lpk_costume_count(const struct LpkHandle *handle, size_t character);
+
+// 服装名称, 越界时返回 `NULL`, 字符串在句柄释放前有效
+const char *lpk_costume_name(const struct LpkHandle *handle, size_t character, size_t costume);
+
+// 服装的模型 JSON 在压缩包中的条目名, 越界时返回 `NULL`, 字符串在句柄释放前有效
+const char *lpk_costume_path(const struct LpkHandle *handle,
+                             size_t character,
+                             size_t costume);
+
+// 解压到指定目录, 成功返回 `LPK_OK`, 失败返回 `LPK_ERROR`
+int32_t lpk_extract(struct LpkHandle *handle, const char *output_dir);
+
+// 当前线程最后一次失败的错误信息, 没有错误时返回 `NULL`
+//
+// 调用除 `lpk_last_error` 和 `lpk_last_error_code` 之外的函数都会清除错误, 字符串在下一次调用这些函数前有效.
+const char *lpk_last_error(void);
+
+// 当前线程最后一次失败的错误码, 与 `LpkError::code` 相同, 没有错误时返回 `NULL`
+const char *lpk_last_error_code(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LPK_H */
@@ -0,0 +1,267 @@
+//! `lpk` 的 C 接口
+//!
+//! 失败时返回 `NULL` 或 `LPK_ERROR`, 错误信息通过 [`lpk_last_error`] 获取.
+//!
+//! 捕获 panic 需要以展开方式编译: 使用 `--profile ffi-release` 或 debug 构建时, 内部的 panic 会被捕获并记录为 `panic` 错误码.
+//! 工作区的 release 配置使用 `panic = "abort"`, 以此构建的动态库在 panic 时会直接终止宿主进程.
+//! 头文件位于 `include/lpk.h`, 由 cbindgen 生成.
+
+#![allow(clippy::missing_safety_doc)]
+
+use lpk::{LpkError, LpkLoader, MLveConfig};
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    fs::File,
+    io::Cursor,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+    ptr::null,
+};
+
+/// 操作成功
+pub const LPK_OK: i32 = 0;
+/// 操作失败, 错误信息见 `lpk_last_error`
+pub const LPK_ERROR: i32 = -1;
+
+thread_local! {
+    /// 当前线程最后一次失败的错误信息和错误码
+    static LAST_ERROR: RefCell<Option<(CString, CString)>> = const { RefCell::new(None) };
+}
+
+/// 不透明的LPK加载器句柄, 使用 `lpk_close` 释放
+pub struct LpkHandle {
+    loader: Loader,
+    characters: Vec<CharacterStrings>,
+}
+
+/// 从文件或内存打开的加载器
+enum Loader {
+    File(LpkLoader<File>),
+    Buffer(LpkLoader<Cursor<Vec<u8>>>),
+}
+
+/// 返回给 C 的角色字符串, 在句柄释放前有效
+struct CharacterStrings {
+    id: CString,
+    name: CString,
+    costumes: Vec<(CString, CString)>,
+}
+
+impl LpkHandle {
+    fn new(loader: Loader) -> Self {
+        let config = match &loader {
+            Loader::File(o) => o.mlve_config(),
+            Loader::Buffer(o) => o.mlve_config(),
+        };
+        LpkHandle { characters: character_strings(config), loader }
+    }
+}
+
+fn character_strings(config: &MLveConfig) -> Vec<CharacterStrings> {
+    config
+        .list
+        .iter()
+        .map(|chara| CharacterStrings {
+            id: c_string(&chara.id),
+            name: c_string(&chara.character),
+            costumes: chara.costume.iter().map(|c| (c_string(&c.name), c_string(&c.path))).collect(),
+        })
+        .collect()
+}
+
+/// 去掉内部的 NUL 字符后转换为 C 字符串
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+fn set_error(code: &str, message: &str) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some((c_string(code), c_string(message))));
+}
+
+/// C 接口的错误
+enum FfiError {
+    Lpk(LpkError),
+    /// 参数为空指针或不是 UTF-8 字符串
+    InvalidArgument(String),
+}
+
+impl From<LpkError> for FfiError {
+    fn from(e: LpkError) -> Self {
+        FfiError::Lpk(e)
+    }
+}
+
+/// 清除当前线程的错误后执行 `f`, 记录返回的错误, 以展开方式编译时还会捕获 panic
+fn guard<T>(fallback: T, f: impl FnOnce() -> Result<T, FfiError>) -> T {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(o)) => o,
+        Ok(Err(FfiError::Lpk(e))) => {
+            set_error(e.code(), &e.to_string());
+            fallback
+        }
+        Ok(Err(FfiError::InvalidArgument(message))) => {
+            set_error("invalid_argument", &message);
+            fallback
+        }
+        Err(_) => {
+            set_error("panic", "内部错误");
+            fallback
+        }
+    }
+}
+
+/// 读取 C 字符串参数, 空指针或非 UTF-8 时记录错误
+unsafe fn str_arg<'a>(name: &str, ptr: *const c_char) -> Result<&'a str, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::InvalidArgument(format!("{name} 为空指针")));
+    }
+    CStr::from_ptr(ptr).to_str().map_err(|_| FfiError::InvalidArgument(format!("{name} 不是 UTF-8 字符串")))
+}
+
+/// 读取缓冲区参数, 长度为 0 时允许空指针
+unsafe fn bytes_arg<'a>(name: &str, data: *const u8, len: usize) -> Result<&'a [u8], FfiError> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(FfiError::InvalidArgument(format!("{name} 为空指针"))),
+        (false, _) => Ok(std::slice::from_raw_parts(data, len)),
+    }
+}
+
+fn into_handle(handle: LpkHandle) -> *mut LpkHandle {
+    Box::into_raw(Box::new(handle))
+}
+
+/// 打开LPK文件, Steam Workshop LPK 读取同目录的 config.json, 失败时返回 `NULL`
+#[no_mangle]
+pub unsafe extern "C" fn lpk_open(path: *const c_char) -> *mut LpkHandle {
+    guard(std::ptr::null_mut(), || {
+        let path = str_arg("path", path)?;
+        Ok(into_handle(LpkHandle::new(Loader::File(LpkLoader::open(Path::new(path))?))))
+    })
+}
+
+/// 打开LPK文件并使用指定的 config.json, 失败时返回 `NULL`
+#[no_mangle]
+pub unsafe extern "C" fn lpk_open_with_config(path: *const c_char, config_path: *const c_char) -> *mut LpkHandle {
+    guard(std::ptr::null_mut(), || {
+        let path = str_arg("path", path)?;
+        let config_path = str_arg("config_path", config_path)?;
+        let loader = LpkLoader::open_with_config(Path::new(path), Path::new(config_path))?;
+        Ok(into_handle(LpkHandle::new(Loader::File(loader))))
+    })
+}
+
+/// 从内存打开LPK, 数据会被复制, 失败时返回 `NULL`
+///
+/// `config` 是 config.json 的内容, 可以为 `NULL`, 此时 Steam Workshop LPK 从已知明文恢复密钥.
+#[no_mangle]
+pub unsafe extern "C" fn lpk_open_buffer(data: *const u8, len: usize, config: *const u8, config_len: usize) -> *mut LpkHandle {
+    guard(std::ptr::null_mut(), || {
+        let reader = Cursor::new(bytes_arg("data", data, len)?.to_vec());
+        let loader = match config.is_null() {
+            true => LpkLoader::from_reader_with_key_recovery(reader)?,
+            false => LpkLoader::from_reader_with_config_bytes(reader, bytes_arg("config", config, config_len)?)?,
+        };
+        Ok(into_handle(LpkHandle::new(Loader::Buffer(loader))))
+    })
+}
+
+/// 释放句柄, `handle` 可以为 `NULL`
+#[no_mangle]
+pub unsafe extern "C" fn lpk_close(handle: *mut LpkHandle) {
+    guard((), || {
+        if !handle.is_null() {
+            drop(Box::from_raw(handle));
+        }
+        Ok(())
+    })
+}
+
+/// 角色数量
+#[no_mangle]
+pub unsafe extern "C" fn lpk_character_count(handle: *const LpkHandle) -> usize {
+    guard(0, || Ok(handle.as_ref().map(|h| h.characters.len()).unwrap_or(0)))
+}
+
+/// 角色 id, 越界时返回 `NULL`, 字符串在句柄释放前有效
+#[no_mangle]
+pub unsafe extern "C" fn lpk_character_id(handle: *const LpkHandle, character: usize) -> *const c_char {
+    character_str(handle, character, |c| &c.id)
+}
+
+/// 角色名称, 越界时返回 `NULL`, 字符串在句柄释放前有效
+#[no_mangle]
+pub unsafe extern "C" fn lpk_character_name(handle: *const LpkHandle, character: usize) -> *const c_char {
+    character_str(handle, character, |c| &c.name)
+}
+
+/// 角色的服装数量, 越界时返回 0
+#[no_mangle]
+pub unsafe extern "C" fn lpk_costume_count(handle: *const LpkHandle, character: usize) -> usize {
+    guard(0, || Ok(character_at(handle, character).map(|c| c.costumes.len()).unwrap_or(0)))
+}
+
+/// 服装名称, 越界时返回 `NULL`, 字符串在句柄释放前有效
+#[no_mangle]
+pub unsafe extern "C" fn lpk_costume_name(handle: *const LpkHandle, character: usize, costume: usize) -> *const c_char {
+    costume_str(handle, character, costume, |(name, _)| name)
+}
+
+/// 服装的模型 JSON 在压缩包中的条目名, 越界时返回 `NULL`, 字符串在句柄释放前有效
+#[no_mangle]
+pub unsafe extern "C" fn lpk_costume_path(handle: *const LpkHandle, character: usize, costume: usize) -> *const c_char {
+    costume_str(handle, character, costume, |(_, path)| path)
+}
+
+unsafe fn character_at<'a>(handle: *const LpkHandle, character: usize) -> Option<&'a CharacterStrings> {
+    handle.as_ref()?.characters.get(character)
+}
+
+unsafe fn character_str(
+    handle: *const LpkHandle,
+    character: usize,
+    f: impl FnOnce(&CharacterStrings) -> &CString,
+) -> *const c_char {
+    guard(null(), || Ok(character_at(handle, character).map(|c| f(c).as_ptr()).unwrap_or(null())))
+}
+
+unsafe fn costume_str(
+    handle: *const LpkHandle,
+    character: usize,
+    costume: usize,
+    f: impl FnOnce(&(CString, CString)) -> &CString,
+) -> *const c_char {
+    guard(null(), || {
+        Ok(character_at(handle, character).and_then(|c| c.costumes.get(costume)).map(|c| f(c).as_ptr()).unwrap_or(null()))
+    })
+}
+
+/// 解压到指定目录, 成功返回 `LPK_OK`, 失败返回 `LPK_ERROR`
+#[no_mangle]
+pub unsafe extern "C" fn lpk_extract(handle: *mut LpkHandle, output_dir: *const c_char) -> i32 {
+    guard(LPK_ERROR, || {
+        let handle = handle.as_mut().ok_or_else(|| FfiError::InvalidArgument("handle 为空指针".to_string()))?;
+        let output_dir = Path::new(str_arg("output_dir", output_dir)?);
+        match &mut handle.loader {
+            Loader::File(o) => o.extract(output_dir)?,
+            Loader::Buffer(o) => o.extract(output_dir)?,
+        }
+        Ok(LPK_OK)
+    })
+}
+
+/// 当前线程最后一次失败的错误信息, 没有错误时返回 `NULL`
+///
+/// 调用除 `lpk_last_error` 和 `lpk_last_error_code` 之外的函数都会清除错误, 字符串在下一次调用这些函数前有效.
+#[no_mangle]
+pub extern "C" fn lpk_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map(|(_, message)| message.as_ptr()).unwrap_or(null()))
+}
+
+/// 当前线程最后一次失败的错误码, 与 `LpkError::code` 相同, 没有错误时返回 `NULL`
+#[no_mangle]
+pub extern "C" fn lpk_last_error_code() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map(|(code, _)| code.as_ptr()).unwrap_or(null()))
+}
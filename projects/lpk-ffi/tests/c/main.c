@@ -0,0 +1,70 @@
+/* 通过 C 接口打开, 列出并解压 LPK: main <lpk> <output> */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "lpk.h"
+
+#define CHECK(cond)                                                                     \
+    do {                                                                                \
+        if (!(cond)) {                                                                  \
+            const char *error = lpk_last_error();                                       \
+            fprintf(stderr, "%s:%d: %s (%s)\n", __FILE__, __LINE__, #cond,             \
+                    error ? error : "no error");                                        \
+            return 1;                                                                   \
+        }                                                                               \
+    } while (0)
+
+static unsigned char *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    *len = (size_t)ftell(file);
+    fseek(file, 0, SEEK_SET);
+    unsigned char *data = malloc(*len);
+    if (data && fread(data, 1, *len, file) != *len) {
+        free(data);
+        data = NULL;
+    }
+    fclose(file);
+    return data;
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 3);
+
+    LpkHandle *lpk = lpk_open(argv[1]);
+    CHECK(lpk != NULL);
+    CHECK(lpk_last_error() == NULL);
+    CHECK(lpk_character_count(lpk) == 1);
+    printf("%s %s %zu\n", lpk_character_id(lpk, 0), lpk_character_name(lpk, 0), lpk_costume_count(lpk, 0));
+    for (size_t i = 0; i < lpk_costume_count(lpk, 0); i++) {
+        CHECK(lpk_costume_path(lpk, 0, i) != NULL);
+        printf("%s\n", lpk_costume_name(lpk, 0, i));
+    }
+    CHECK(lpk_character_name(lpk, 1) == NULL);
+    CHECK(lpk_costume_name(lpk, 0, 2) == NULL);
+    CHECK(lpk_extract(lpk, argv[2]) == LPK_OK);
+    CHECK(lpk_extract(lpk, NULL) == LPK_ERROR);
+    CHECK(strcmp(lpk_last_error_code(), "invalid_argument") == 0);
+    lpk_close(lpk);
+
+    /* 从内存打开 */
+    size_t len = 0;
+    unsigned char *data = read_file(argv[1], &len);
+    CHECK(data != NULL);
+    lpk = lpk_open_buffer(data, len, NULL, 0);
+    free(data);
+    CHECK(lpk != NULL);
+    CHECK(lpk_costume_count(lpk, 0) == 2);
+    lpk_close(lpk);
+
+    /* 打开失败时返回 NULL 并记录错误 */
+    CHECK(lpk_open("missing.lpk") == NULL);
+    CHECK(lpk_last_error() != NULL);
+    printf("%s\n", lpk_last_error_code());
+    lpk_close(NULL);
+    return 0;
+}
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+#[path = "../../lpk-core/tests/common/mod.rs"]
+mod common;
+
+/// 头文件需要与 cbindgen 生成的内容一致, 设置 `LPK_UPDATE_HEADER` 时重新生成
+#[test]
+fn test_header_is_up_to_date() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+    let mut header = Vec::new();
+    cbindgen::Builder::new().with_crate(root).with_config(config).generate().unwrap().write(&mut header);
+    let path = root.join("include/lpk.h");
+    if std::env::var_os("LPK_UPDATE_HEADER").is_some() {
+        std::fs::write(&path, &header).unwrap();
+    }
+    assert_eq!(String::from_utf8(header).unwrap(), std::fs::read_to_string(path).unwrap());
+}
+
+/// 动态库所在的目录, 与测试程序同在 `target/<profile>/deps`
+fn library_dir() -> PathBuf {
+    std::env::current_exe().unwrap().parent().unwrap().to_path_buf()
+}
+
+/// 编译 `tests/c/main.c` 并链接动态库, 在生成的LPK上运行
+#[test]
+#[cfg(unix)]
+fn test_c_api() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let (temp, bytes) = common::packed_model(&[("hiyori", "default"), ("hiyori", "summer")]);
+    let lpk_path = temp.path().join("hiyori.lpk");
+    std::fs::write(&lpk_path, bytes).unwrap();
+
+    let lib_dir = library_dir();
+    let program = temp.path().join("c_api");
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = std::process::Command::new(compiler)
+        .arg(root.join("tests/c/main.c"))
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(format!("-I{}", root.join("include").display()))
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-llpk_ffi")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to compile tests/c/main.c");
+
+    let output = temp.path().join("output");
+    let result = std::process::Command::new(&program).arg(&lpk_path).arg(&output).output().unwrap();
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    assert!(result.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&result.stderr));
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), ["0 hiyori 2", "default", "summer", "io"]);
+    assert!(output.join("hiyori/hiyori-default.model3.json").exists());
+    assert!(output.join("hiyori/hiyori-summer.model3.json").exists());
+}
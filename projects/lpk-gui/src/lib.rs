@@ -1,9 +1,10 @@
 use crate::utils::scan_directory_for_lpk;
 use dioxus::prelude::*;
-use lpk::{LpkError, LpkLoader};
+use lpk::{ExtractEvent, LpkError, LpkLoader};
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 mod utils;
@@ -189,6 +190,7 @@ impl AppState {
 
         let mut success_count = 0;
         let mut error_count = 0;
+        let mut summary = ExtractSummary::default();
 
         for file in files {
             let file_path = file.to_string_lossy().to_string();
@@ -200,8 +202,10 @@ impl AppState {
 
             // 解压文件
             match extract_lpk_file(&file) {
-                Ok(_) => {
+                Ok(s) => {
                     success_count += 1;
+                    summary.files += s.files;
+                    summary.warnings.extend(s.warnings);
                 }
                 Err(err) => {
                     error_count += 1;
@@ -210,17 +214,51 @@ impl AppState {
             }
         }
 
-        self.status_message = format!("解压完成: {} 成功, {} 失败", success_count, error_count);
+        self.status_message = format!(
+            "解压完成: {} 成功, {} 失败, 共写入 {} 个文件, {} 条警告",
+            success_count,
+            error_count,
+            summary.files,
+            summary.warnings.len()
+        );
         self.is_processing = false;
     }
 }
 
+// 单个LPK文件的解压统计
+#[derive(Clone, Default)]
+struct ExtractSummary {
+    // 写入的文件数量
+    files: usize,
+    // 解压过程中的警告
+    warnings: Vec<String>,
+}
+
+impl ExtractSummary {
+    fn record(&mut self, event: &ExtractEvent) {
+        match event {
+            ExtractEvent::FileWritten { .. } => self.files += 1,
+            ExtractEvent::Warning { message } => self.warnings.push(message.clone()),
+            _ => {}
+        }
+    }
+}
+
 // 解压单个LPK文件
-fn extract_lpk_file(lpk_path: &Path) -> Result<(), LpkError> {
+fn extract_lpk_file(lpk_path: &Path) -> Result<ExtractSummary, LpkError> {
     match lpk_path.parent() {
         Some(s) => {
             let mut loader = LpkLoader::open(lpk_path)?;
-            loader.extract(s)
+            let summary = Arc::new(Mutex::new(ExtractSummary::default()));
+            let observer = summary.clone();
+            loader.set_observer(move |event: &ExtractEvent| {
+                if let Ok(mut summary) = observer.lock() {
+                    summary.record(event);
+                }
+            });
+            loader.extract(s)?;
+            let summary = summary.lock().map(|s| s.clone()).unwrap_or_default();
+            Ok(summary)
         }
         None => Err(LpkError::ConfigMissing),
     }